@@ -0,0 +1,52 @@
+//! Criterion benchmarks of each stage, over the programs `rlux bench` times.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rlux::interpreter::{Interpreter, Output};
+use rlux::optimizer;
+use rlux::program::Program;
+use rlux::resolver::Resolver;
+use rlux::scanner::Scanner;
+
+const PROGRAMS: [(&str, &str); 4] = [
+    ("fib", include_str!("programs/fib.lux")),
+    ("loops", include_str!("programs/loops.lux")),
+    ("closures", include_str!("programs/closures.lux")),
+    ("strings", include_str!("programs/strings.lux")),
+];
+
+fn scanner(c: &mut Criterion) {
+    let mut group = c.benchmark_group("scanner");
+    for (name, source) in PROGRAMS {
+        group.bench_function(name, |b| b.iter(|| Scanner::new(black_box(source)).run()));
+    }
+    group.finish();
+}
+
+fn parser(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parser");
+    for (name, source) in PROGRAMS {
+        let tokens = Scanner::new(source).run();
+        group.bench_function(name, |b| b.iter(|| Program::parse(black_box(&tokens)).unwrap()));
+    }
+    group.finish();
+}
+
+fn interpreter(c: &mut Criterion) {
+    let mut group = c.benchmark_group("interpreter");
+    group.sample_size(20);
+    for (name, source) in PROGRAMS {
+        let program = optimizer::optimize(Program::parse(&Scanner::new(source).run()).unwrap());
+        Resolver::new().run(&program).unwrap();
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut interpreter = Interpreter::new();
+                interpreter.set_output(Output::captured());
+                interpreter.run(black_box(&program)).unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, scanner, parser, interpreter);
+criterion_main!(benches);
@@ -2,10 +2,16 @@ pub mod expr;
 pub mod stmt;
 
 
-pub use expr::{Expr, UnaryOp, BinaryOp};
+pub use expr::{Expr, Slot, UnaryOp, BinaryOp};
 pub use stmt::Stmt;
 
 
 pub trait StructuralPrinter {
     fn print_structural(&self) -> String;
 }
+
+impl<T: StructuralPrinter> StructuralPrinter for crate::position::WithSpan<T> {
+    fn print_structural(&self) -> String {
+        self.value.print_structural()
+    }
+}
@@ -1,19 +1,26 @@
+use std::{cell::Cell, rc::Rc};
+
 use super::StructuralPrinter;
+use crate::position::{Span, WithSpan};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
     Number(f64),
-    String(String),
+    /// The parser interns literal strings, see `crate::intern`.
+    String(Rc<str>),
     Grouping(Box<Expr>),
     True,
     False,
     Nil,
-    LogicalOr(Box<Expr>, Box<Expr>),
-    LogicalAnd(Box<Expr>, Box<Expr>),
+    /// Logical operators know where their keyword is, so that coverage can
+    /// tell which of them short-circuited.
+    LogicalOr(Box<Expr>, Span, Box<Expr>),
+    LogicalAnd(Box<Expr>, Span, Box<Expr>),
     Unary(UnaryOp, Box<Expr>),
     Binary(Box<Expr>, BinaryOp, Box<Expr>),
-    Variable(String),
-    Assignment(String, Box<Expr>),
+    /// Variables know where they are so that tools can find their uses.
+    Variable(WithSpan<String>, Slot),
+    Assignment(WithSpan<String>, Slot, Box<Expr>),
     Call(Box<Expr>, Vec<Expr>)
 }
 
@@ -22,8 +29,8 @@ impl Expr {
         Expr::Number(n)
     }
 
-    pub fn string(s: String) -> Expr {
-        Expr::String(s)
+    pub fn string(s: &str) -> Expr {
+        Expr::String(Rc::from(s))
     }
 
     pub fn grouping(expr: Expr) -> Expr {
@@ -50,20 +57,20 @@ impl Expr {
         Expr::Binary(Box::new(left), op, Box::new(right))
     }
 
-    pub fn logical_or(left: Expr, right: Expr) -> Expr {
-        Expr::LogicalOr(Box::new(left), Box::new(right))
+    pub fn logical_or(left: Expr, operator: Span, right: Expr) -> Expr {
+        Expr::LogicalOr(Box::new(left), operator, Box::new(right))
     }
 
-    pub fn logical_and(left: Expr, right: Expr) -> Expr {
-        Expr::LogicalAnd(Box::new(left), Box::new(right))
+    pub fn logical_and(left: Expr, operator: Span, right: Expr) -> Expr {
+        Expr::LogicalAnd(Box::new(left), operator, Box::new(right))
     }
 
-    pub fn variable(name: String) -> Expr {
-        Expr::Variable(name)
+    pub fn variable(name: WithSpan<String>) -> Expr {
+        Expr::Variable(name, Slot::default())
     }
 
-    pub fn assignment(name: String, expr: Expr) -> Expr {
-        Expr::Assignment(name, Box::new(expr))
+    pub fn assignment(name: WithSpan<String>, expr: Expr) -> Expr {
+        Expr::Assignment(name, Slot::default(), Box::new(expr))
     }
 
     pub fn call(callee: Expr, arguments: Vec<Expr>) -> Expr {
@@ -71,11 +78,27 @@ impl Expr {
     }
 }
 
+/// Where a local variable is found when the program runs: how many scopes out
+/// from the current one, and its index in that scope. The resolver fills it
+/// in, globals have none and are looked up by name.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Slot(Cell<Option<(usize, usize)>>);
+
+impl Slot {
+    pub fn get(&self) -> Option<(usize, usize)> {
+        self.0.get()
+    }
+
+    pub fn set(&self, depth: usize, index: usize) {
+        self.0.set(Some((depth, index)));
+    }
+}
+
 impl StructuralPrinter for Expr {
     fn print_structural(&self) -> String {
         match self {
-            Expr::LogicalOr(left, right) => format!("({} or {})", left.print_structural(), right.print_structural()),
-            Expr::LogicalAnd(left, right) => format!("({} and {})", left.print_structural(), right.print_structural()),
+            Expr::LogicalOr(left, _, right) => format!("({} or {})", left.print_structural(), right.print_structural()),
+            Expr::LogicalAnd(left, _, right) => format!("({} and {})", left.print_structural(), right.print_structural()),
             Expr::Number(n) => n.to_string(),
             Expr::String(s) => format!("\"{}\"", s),
             Expr::Nil => "nil".to_string(),
@@ -91,8 +114,8 @@ impl StructuralPrinter for Expr {
                 op.print_structural(),
                 right.print_structural()
             ),
-            Expr::Variable(name) => name.clone(),
-            Expr::Assignment(name, expr) => format!("({} = {})", name, expr.print_structural()),
+            Expr::Variable(name, _) => name.value.clone(),
+            Expr::Assignment(name, _, expr) => format!("({} = {})", name.value, expr.print_structural()),
             Expr::Call(callee, arguments ) => 
                 format!("(({})({}))", callee.print_structural(), arguments.iter().map(|a| a.print_structural()).collect::<Vec<String>>().join(", ")),
         }
@@ -136,7 +159,7 @@ pub enum BinaryOp {
 }
 
 impl BinaryOp {
-    fn print(&self) -> String {
+    pub fn print(&self) -> String {
         let s = match self {
             BinaryOp::Equals => "==",
             BinaryOp::NotEquals => "!=",
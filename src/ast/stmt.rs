@@ -1,15 +1,22 @@
+use std::rc::Rc;
+
 use super::{Expr, StructuralPrinter};
+use crate::position::WithSpan;
 
+/// Statements know where they are in the source so that errors, breakpoints
+/// and tools can point at them.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Stmt {
     Expression(Expr),
     Print(Expr),
-    If(Expr, Box<Stmt>, Option<Box<Stmt>>),
-    While(Expr, Box<Stmt>),
-    Var(String, Expr),
-    Block(Vec<Stmt>),
+    If(Expr, Box<WithSpan<Stmt>>, Option<Box<WithSpan<Stmt>>>),
+    While(Expr, Box<WithSpan<Stmt>>),
+    /// `for (initializer; condition; increment) body`, the initializer is scoped to the loop.
+    For(Option<Box<WithSpan<Stmt>>>, Option<Expr>, Option<Expr>, Box<WithSpan<Stmt>>),
+    Var(WithSpan<Rc<str>>, Expr),
+    Block(Vec<WithSpan<Stmt>>),
     Return(Expr),
-    Function(String, Vec<String>, Box<Stmt>)
+    Function(WithSpan<Rc<str>>, Vec<WithSpan<Rc<str>>>, Box<WithSpan<Stmt>>)
 }
 
 impl Stmt {
@@ -19,16 +26,16 @@ impl Stmt {
     pub fn print(expr: Expr) -> Self {
         Stmt::Print(expr)
     }
-    pub fn var(name: String, expr: Expr) -> Self {
+    pub fn var(name: WithSpan<Rc<str>>, expr: Expr) -> Self {
         Stmt::Var(name, expr)
     }
-    pub fn block(stmts: Vec<Stmt>) -> Self {
+    pub fn block(stmts: Vec<WithSpan<Stmt>>) -> Self {
         Stmt::Block(stmts)
     }
-    pub fn if_(cond: Expr, then: Stmt, else_: Option<Stmt>) -> Self {
+    pub fn if_(cond: Expr, then: WithSpan<Stmt>, else_: Option<WithSpan<Stmt>>) -> Self {
         Stmt::If(cond, Box::new(then), else_.map(Box::new))
     }
-    pub fn while_(cond: Expr, body: Stmt) -> Self {
+    pub fn while_(cond: Expr, body: WithSpan<Stmt>) -> Self {
         Stmt::While(cond, Box::new(body))
     }
 }
@@ -36,13 +43,25 @@ impl Stmt {
 impl StructuralPrinter for Stmt {
     fn print_structural(&self) -> String {
         match self {
-            Stmt::Function(name, args, body) => format!("fun {}({}){}", name, args.join(", "), body.print_structural()),
+            Stmt::Function(name, args, body) => format!(
+                "fun {}({}){}",
+                name.value,
+                args.iter().map(|a| &*a.value).collect::<Vec<_>>().join(", "),
+                body.print_structural()
+            ),
             Stmt::Expression(expr) => format!("{};", expr.print_structural()),
             Stmt::Print(expr) => format!("print {};", expr.print_structural()),
-            Stmt::Var(name, expr) => format!("var {} = {};", name, expr.print_structural()),
+            Stmt::Var(name, expr) => format!("var {} = {};", name.value, expr.print_structural()),
             Stmt::Block(stmts) => format!("{{\n{}\n}}", stmts.iter().map(|s| s.print_structural()).collect::<Vec<String>>().join(", ")),
             Stmt::If(cond, then, else_) => format!("if({}) {} else {}", cond.print_structural(), then.print_structural(), else_.as_ref().map(|e| e.print_structural()).unwrap_or("None".to_string())),
             Stmt::While(cond, body) => format!("while ({}) {}", cond.print_structural(), body.print_structural()),
+            Stmt::For(init, cond, incr, body) => format!(
+                "for ({} {}; {}) {}",
+                init.as_ref().map(|i| i.print_structural()).unwrap_or(";".to_string()),
+                cond.as_ref().map(|c| c.print_structural()).unwrap_or_default(),
+                incr.as_ref().map(|i| i.print_structural()).unwrap_or_default(),
+                body.print_structural()
+            ),
             Stmt::Return(expr) => format!("return {};", expr.print_structural()),
         }
     }
@@ -0,0 +1,214 @@
+//! Timing lux programs for `rlux bench`, to catch performance regressions.
+//!
+//! Each program runs several times in a fresh interpreter, from scanning to
+//! the end of the program. The results can be saved as a JSON baseline and
+//! later runs compared against it.
+
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::{
+    check,
+    interpreter::{Interpreter, Output},
+    optimizer,
+    position::LineOffsets,
+    program::Program,
+    resolver::Resolver,
+    scanner::Scanner,
+};
+
+/// Version of the baseline format written by `to_json`.
+const BASELINE_VERSION: u64 = 1;
+
+/// Summary of the run times of a program, in milliseconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    pub runs: usize,
+    pub mean: f64,
+    pub median: f64,
+    pub stddev: f64,
+}
+
+impl Stats {
+    pub fn new(samples: &[Duration]) -> Self {
+        let mut ms: Vec<f64> = samples.iter().map(|s| s.as_secs_f64() * 1000.0).collect();
+        ms.sort_by(f64::total_cmp);
+        let runs = ms.len();
+        if runs == 0 {
+            return Stats { runs, mean: 0.0, median: 0.0, stddev: 0.0 };
+        }
+        let mean = ms.iter().sum::<f64>() / runs as f64;
+        let median = if runs.is_multiple_of(2) { (ms[runs / 2 - 1] + ms[runs / 2]) / 2.0 } else { ms[runs / 2] };
+        let variance = ms.iter().map(|m| (m - mean).powi(2)).sum::<f64>() / runs as f64;
+        Stats { runs, mean, median, stddev: variance.sqrt() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Benchmark {
+    pub name: String,
+    pub stats: Stats,
+}
+
+/// Run `source` `runs` times. Fails with a message if the program has errors.
+pub fn measure(source: &str, runs: usize) -> Result<Stats, String> {
+    let line_offsets = LineOffsets::new(source);
+    if let Some(error) = check::check(source).first() {
+        return Err(format!("line {}: {}", line_offsets.line(error.span.start), error.message));
+    }
+
+    let mut samples = Vec::with_capacity(runs);
+    for _ in 0..runs {
+        let start = Instant::now();
+        let program = Program::parse(&Scanner::new(source).run()).expect("checked programs parse");
+        let program = optimizer::optimize(program);
+        Resolver::new().run(&program).expect("checked programs resolve");
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Output::captured());
+        if let Err(err) = interpreter.run(&program) {
+            let line = line_offsets.line(interpreter.error_span().start);
+            return Err(format!("line {}: {:?}", line, err));
+        }
+        samples.push(start.elapsed());
+    }
+    Ok(Stats::new(&samples))
+}
+
+/// Benchmark a file, naming it after the file without its extension.
+pub fn measure_file(path: &Path, runs: usize) -> io::Result<Result<Benchmark, String>> {
+    let source = std::fs::read_to_string(path)?;
+    let name = path.file_stem().map_or_else(|| path.display().to_string(), |s| s.to_string_lossy().into_owned());
+    Ok(measure(&source, runs).map(|stats| Benchmark { name, stats }))
+}
+
+pub fn to_json(benchmarks: &[Benchmark]) -> Value {
+    let benchmarks: Vec<Value> = benchmarks
+        .iter()
+        .map(|b| {
+            json!({
+                "name": b.name,
+                "runs": b.stats.runs,
+                "mean_ms": b.stats.mean,
+                "median_ms": b.stats.median,
+                "stddev_ms": b.stats.stddev,
+            })
+        })
+        .collect();
+    json!({ "version": BASELINE_VERSION, "benchmarks": benchmarks })
+}
+
+/// Read a baseline written by `to_json`.
+pub fn from_json(json: &str) -> Result<Vec<Benchmark>, String> {
+    let value: Value = serde_json::from_str(json).map_err(|err| err.to_string())?;
+    match value["version"].as_u64() {
+        Some(BASELINE_VERSION) => {}
+        Some(version) => return Err(format!("unsupported baseline version {}", version)),
+        None => return Err("missing baseline version".to_string()),
+    }
+    let benchmarks = value["benchmarks"].as_array().ok_or("missing benchmarks")?;
+    benchmarks
+        .iter()
+        .map(|b| {
+            let number = |key: &str| b[key].as_f64().ok_or(format!("benchmark without `{}`", key));
+            Ok(Benchmark {
+                name: b["name"].as_str().ok_or("benchmark without `name`")?.to_string(),
+                stats: Stats {
+                    runs: number("runs")? as usize,
+                    mean: number("mean_ms")?,
+                    median: number("median_ms")?,
+                    stddev: number("stddev_ms")?,
+                },
+            })
+        })
+        .collect()
+}
+
+/// How much slower the benchmark's mean is than in the baseline, in percent.
+/// `None` if the baseline does not have it.
+pub fn change(benchmark: &Benchmark, baseline: &[Benchmark]) -> Option<f64> {
+    let before = baseline.iter().find(|b| b.name == benchmark.name)?;
+    Some((benchmark.stats.mean - before.stats.mean) / before.stats.mean * 100.0)
+}
+
+/// The benchmarks that got more than `threshold` percent slower than in the baseline.
+pub fn regressions<'a>(benchmarks: &'a [Benchmark], baseline: &[Benchmark], threshold: f64) -> Vec<&'a Benchmark> {
+    benchmarks.iter().filter(|b| change(b, baseline).is_some_and(|c| c > threshold)).collect()
+}
+
+/// A table of the benchmarks, with their change against the baseline if there is one.
+pub fn report<W: Write>(benchmarks: &[Benchmark], baseline: Option<&[Benchmark]>, out: &mut W) -> io::Result<()> {
+    write!(out, "{:<20} {:>6} {:>12} {:>12} {:>12}", "benchmark", "runs", "mean ms", "median ms", "stddev ms")?;
+    if baseline.is_some() {
+        write!(out, " {:>12}", "vs baseline")?;
+    }
+    writeln!(out)?;
+    for benchmark in benchmarks {
+        let stats = &benchmark.stats;
+        write!(
+            out,
+            "{:<20} {:>6} {:>12.3} {:>12.3} {:>12.3}",
+            benchmark.name, stats.runs, stats.mean, stats.median, stats.stddev
+        )?;
+        if let Some(baseline) = baseline {
+            match change(benchmark, baseline) {
+                Some(change) => write!(out, " {:>+11.1}%", change)?,
+                None => write!(out, " {:>12}", "new")?,
+            }
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn benchmark(name: &str, mean: f64) -> Benchmark {
+        Benchmark { name: name.to_string(), stats: Stats { runs: 3, mean, median: mean, stddev: 0.5 } }
+    }
+
+    #[test]
+    fn test_stats() {
+        let ms = |ms: u64| Duration::from_millis(ms);
+        let stats = Stats::new(&[ms(4), ms(1), ms(3), ms(2)]);
+        assert_eq!(stats.runs, 4);
+        assert!((stats.mean - 2.5).abs() < 1e-9);
+        assert!((stats.median - 2.5).abs() < 1e-9);
+        assert!((stats.stddev - 1.25f64.sqrt()).abs() < 1e-9);
+        assert_eq!(Stats::new(&[ms(5), ms(1), ms(2)]).median, 2.0);
+    }
+
+    #[test]
+    fn test_measure_runs_programs() {
+        let stats = measure("fun f(n) { return n * 2; } print f(21);", 3).unwrap();
+        assert_eq!(stats.runs, 3);
+        assert!(stats.mean > 0.0);
+        assert_eq!(measure("print 1 / 0;", 3).unwrap_err(), "line 1: DivideByZero(\"Cannot divide by zero\")");
+        assert!(measure("print (;", 3).is_err());
+    }
+
+    #[test]
+    fn test_baseline_round_trip_and_comparison() {
+        let baseline = vec![benchmark("fib", 10.0), benchmark("loops", 4.0)];
+        let json = to_json(&baseline).to_string();
+        assert_eq!(from_json(&json).unwrap(), baseline);
+        assert!(from_json("{\"version\": 2, \"benchmarks\": []}").unwrap_err().contains("version 2"));
+
+        let current = vec![benchmark("fib", 12.0), benchmark("loops", 4.2), benchmark("strings", 1.0)];
+        assert!((change(&current[0], &baseline).unwrap() - 20.0).abs() < 1e-9);
+        assert_eq!(change(&current[2], &baseline), None);
+        let slower: Vec<&str> = regressions(&current, &baseline, 10.0).iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(slower, vec!["fib"]);
+
+        let mut out = Vec::new();
+        report(&current, Some(&baseline), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.lines().nth(1).unwrap().ends_with("+20.0%"), "{}", out);
+        assert!(out.lines().nth(3).unwrap().ends_with("new"), "{}", out);
+    }
+}
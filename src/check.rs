@@ -0,0 +1,115 @@
+//! Static checks of lux source: scan, parse and resolve without running anything.
+
+use serde_json::{json, Value};
+
+use crate::{
+    position::{Diagnostic, LineOffsets},
+    program::Program,
+    resolver::Resolver,
+    scanner::Scanner,
+    token::Token,
+};
+
+/// Find all errors in the source that can be found without running it.
+pub fn check(source: &str) -> Vec<Diagnostic> {
+    let tokens = Scanner::new(source).run();
+
+    let mut diagnostics: Vec<Diagnostic> = tokens
+        .iter()
+        .filter_map(|token| {
+            let message = match &token.value {
+                Token::UnterminatedString => "Unterminated string".to_string(),
+                Token::UnknownChar(c) => format!("Unexpected character '{}'", c),
+                _ => return None,
+            };
+            Some(Diagnostic { span: token.span, message })
+        })
+        .collect();
+
+    match Program::parse(&tokens) {
+        Ok(program) => {
+            if let Err(errors) = Resolver::new().run(&program) {
+                diagnostics.extend(errors);
+            }
+        }
+        Err(errors) => {
+            // The parser also complains about bad tokens, but the scanner said it better.
+            let bad_tokens: Vec<_> = diagnostics.iter().map(|d| d.span).collect();
+            diagnostics.extend(errors.into_iter().filter(|d| !bad_tokens.contains(&d.span)));
+        }
+    }
+
+    diagnostics.sort_by_key(|d| d.span.start);
+    diagnostics
+}
+
+/// One line per diagnostic: `file:line:column: error: message`.
+pub fn format_text(file: &str, source: &str, diagnostics: &[Diagnostic]) -> Vec<String> {
+    let line_offsets = LineOffsets::new(source);
+    diagnostics
+        .iter()
+        .map(|d| {
+            format!(
+                "{}:{}:{}: error: {}",
+                file,
+                line_offsets.line(d.span.start),
+                line_offsets.column(d.span.start),
+                d.message
+            )
+        })
+        .collect()
+}
+
+/// One JSON object per diagnostic, for editors and CI.
+pub fn format_json(file: &str, source: &str, diagnostics: &[Diagnostic]) -> Vec<Value> {
+    let line_offsets = LineOffsets::new(source);
+    diagnostics
+        .iter()
+        .map(|d| {
+            json!({
+                "file": file,
+                "line": line_offsets.line(d.span.start),
+                "column": line_offsets.column(d.span.start),
+                "severity": "error",
+                "message": d.message,
+            })
+        })
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_source_has_no_diagnostics() {
+        assert_eq!(check("fun f(a) { return a; }\nprint f(1);"), vec![]);
+    }
+
+    #[test]
+    fn test_reports_parse_errors_in_every_statement() {
+        let source = "print 1 +;\nprint 2;\nvar = 3;";
+        let lines = format_text("a.lux", source, &check(source));
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("a.lux:1:10: error: "));
+        assert!(lines[1].starts_with("a.lux:3:5: error: "));
+    }
+
+    #[test]
+    fn test_reports_scanner_errors_once() {
+        let source = "print 1;\nprint @;";
+        let diagnostics = check(source);
+        assert_eq!(format_text("a.lux", source, &diagnostics), vec!["a.lux:2:7: error: Unexpected character '@'"]);
+    }
+
+    #[test]
+    fn test_reports_resolver_errors() {
+        let source = "{\n  var a = a;\n}";
+        let json = format_json("a.lux", source, &check(source));
+        assert_eq!(json.len(), 1);
+        assert_eq!(json[0]["line"], 2);
+        assert_eq!(json[0]["column"], 11);
+        assert_eq!(json[0]["severity"], "error");
+    }
+}
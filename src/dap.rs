@@ -0,0 +1,611 @@
+//! Debug adapter for lux, speaking the Debug Adapter Protocol over stdio.
+//!
+//! The main thread reads the client's requests. Once the client has launched
+//! a program and is done configuring it, the program runs on a thread of its
+//! own with an `Adapter` as its debugger. The interpreter can not leave that
+//! thread, so from then on the requests are sent there and the adapter answers
+//! them, waiting for the next one whenever the program is paused.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde_json::{json, Value};
+
+use crate::{
+    ast::Stmt,
+    check,
+    debug::{self, Pause, Resume, Stepper},
+    interpreter::{Debugger, Interpreter, Output, RuntimeError},
+    position::{LineOffsets, WithSpan},
+    program::Program,
+    resolver::Resolver,
+    scanner::Scanner,
+};
+
+/// Lux programs have a single thread, which the protocol still wants an id for.
+const THREAD_ID: i64 = 1;
+
+/// Serve a single client on stdin and stdout until it disconnects.
+pub fn run_stdio() -> io::Result<()> {
+    serve(io::stdin().lock(), io::stdout())
+}
+
+/// Answer requests read from `input` until the client disconnects or the input ends.
+pub fn serve<R: BufRead, W: Write + Send + 'static>(mut input: R, output: W) -> io::Result<()> {
+    let mut session = Session::new(Client::new(output));
+    while let Some(request) = read_message(&mut input)? {
+        if request["type"] != "request" {
+            continue;
+        }
+        let disconnect = request["command"] == "disconnect";
+        if let Some(request) = session.forward(request) {
+            session.request(&request)?;
+        }
+        if disconnect {
+            break;
+        }
+    }
+    session.stop();
+    Ok(())
+}
+
+/// Read one message, or `None` at the end of the input.
+fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok();
+        }
+    }
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(io::Error::from)
+}
+
+fn write_message<W: Write + ?Sized>(output: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+/// The writing half of the connection, shared by the main thread and the program's thread.
+#[derive(Clone)]
+struct Client {
+    inner: Arc<Mutex<(Box<dyn Write + Send>, i64)>>,
+}
+
+impl Client {
+    fn new<W: Write + Send + 'static>(output: W) -> Self {
+        Client { inner: Arc::new(Mutex::new((Box::new(output), 0))) }
+    }
+
+    fn send(&self, mut message: Value) -> io::Result<()> {
+        let mut inner = self.inner.lock().expect("a thread panicked while writing");
+        let (output, seq) = &mut *inner;
+        *seq += 1;
+        message["seq"] = json!(*seq);
+        write_message(output, &message)
+    }
+
+    fn respond(&self, request: &Value, body: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": body.is_ok(),
+        });
+        match body {
+            Ok(Value::Null) => {}
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)
+    }
+
+    fn event(&self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+}
+
+/// A program the client asked to debug.
+struct Launch {
+    path: String,
+    source: String,
+    stop_on_entry: bool,
+}
+
+/// The main thread's side: answers requests until the program runs, then forwards them.
+struct Session {
+    client: Client,
+    launch: Option<Launch>,
+    breakpoints: BTreeSet<usize>,
+    configured: bool,
+    /// Where to send requests while the program runs. The program's thread
+    /// takes it when the program ends, so no request goes unanswered.
+    running: Arc<Mutex<Option<Sender<Value>>>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Session {
+    fn new(client: Client) -> Self {
+        Session {
+            client,
+            launch: None,
+            breakpoints: BTreeSet::new(),
+            configured: false,
+            running: Arc::new(Mutex::new(None)),
+            thread: None,
+        }
+    }
+
+    /// Send the request to the running program, or give it back if no program runs.
+    fn forward(&self, request: Value) -> Option<Value> {
+        match &*self.running.lock().expect("the program's thread panicked") {
+            Some(sender) => sender.send(request).err().map(|err| err.0),
+            None => Some(request),
+        }
+    }
+
+    fn request(&mut self, request: &Value) -> io::Result<()> {
+        let arguments = &request["arguments"];
+        match request["command"].as_str().unwrap_or("") {
+            "initialize" => {
+                self.client.respond(request, Ok(json!({ "supportsConfigurationDoneRequest": true })))?;
+                self.client.event("initialized", json!({}))
+            }
+            "launch" => {
+                let launch = launch(arguments);
+                let result = launch.as_ref().map(|_| Value::Null).map_err(|err| err.clone());
+                self.launch = launch.ok();
+                self.client.respond(request, result)?;
+                self.start()
+            }
+            "setBreakpoints" => {
+                self.breakpoints = breakpoint_lines(arguments).collect();
+                self.client.respond(request, Ok(breakpoints(self.breakpoints.iter().copied())))
+            }
+            "configurationDone" => {
+                self.configured = true;
+                self.client.respond(request, Ok(Value::Null))?;
+                self.start()
+            }
+            "threads" => self.client.respond(request, Ok(threads())),
+            "disconnect" | "terminate" => self.client.respond(request, Ok(Value::Null)),
+            "stackTrace" | "scopes" | "variables" | "continue" | "next" | "stepIn" | "stepOut" => {
+                self.client.respond(request, Err("The program is not running".to_string()))
+            }
+            command => self.client.respond(request, Err(format!("Unsupported request `{}`", command))),
+        }
+    }
+
+    /// Run the program once it is launched and configured.
+    fn start(&mut self) -> io::Result<()> {
+        if !self.configured || self.thread.is_some() {
+            return Ok(());
+        }
+        let Some(launch) = self.launch.take() else {
+            return Ok(());
+        };
+        let (sender, receiver) = mpsc::channel();
+        *self.running.lock().expect("no program runs yet") = Some(sender);
+
+        let client = self.client.clone();
+        let running = self.running.clone();
+        let breakpoints = self.breakpoints.clone();
+        self.thread = Some(thread::spawn(move || run(launch, breakpoints, receiver, running, client)));
+        Ok(())
+    }
+
+    /// Stop the program if it still runs and wait for its thread.
+    fn stop(mut self) {
+        self.running.lock().expect("the program's thread panicked").take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Read and check the program of a launch request.
+fn launch(arguments: &Value) -> Result<Launch, String> {
+    let path = arguments["program"].as_str().ok_or("Launch needs a `program` to run")?;
+    let source = fs::read_to_string(path).map_err(|err| format!("Could not read {}: {}", path, err))?;
+    let diagnostics = check::check(&source);
+    if !diagnostics.is_empty() {
+        return Err(check::format_text(path, &source, &diagnostics).join("\n"));
+    }
+    Ok(Launch {
+        path: path.to_string(),
+        stop_on_entry: arguments["stopOnEntry"].as_bool().unwrap_or(false),
+        source,
+    })
+}
+
+fn breakpoint_lines(arguments: &Value) -> impl Iterator<Item = usize> + '_ {
+    let breakpoints = arguments["breakpoints"].as_array().into_iter().flatten();
+    breakpoints.filter_map(|breakpoint| breakpoint["line"].as_u64()).map(|line| line as usize)
+}
+
+fn breakpoints(lines: impl Iterator<Item = usize>) -> Value {
+    let breakpoints: Vec<Value> = lines.map(|line| json!({ "verified": true, "line": line })).collect();
+    json!({ "breakpoints": breakpoints })
+}
+
+fn threads() -> Value {
+    json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })
+}
+
+/// Run the program on its own thread, then tell the client how it ended.
+fn run(
+    launch: Launch,
+    breakpoints: BTreeSet<usize>,
+    requests: Receiver<Value>,
+    running: Arc<Mutex<Option<Sender<Value>>>>,
+    client: Client,
+) {
+    let requests = Rc::new(requests);
+    let output = Output::captured();
+    let line_offsets = LineOffsets::new(&launch.source);
+    let mut stepper = Stepper::new(launch.stop_on_entry);
+    stepper.set_breakpoints(breakpoints);
+
+    let mut interpreter = Interpreter::builder().output(output.clone()).build();
+    interpreter.set_debugger(Some(Box::new(Adapter {
+        stepper,
+        line_offsets: LineOffsets::new(&launch.source),
+        path: launch.path.clone(),
+        requests: requests.clone(),
+        client: client.clone(),
+        output: output.clone(),
+        variables: Vec::new(),
+    })));
+
+    let program = Program::parse(&Scanner::new(&launch.source).run()).expect("launched programs parse");
+    Resolver::new().run(&program).expect("launched programs resolve");
+    let result = interpreter.run(&program);
+
+    // Answer what the client asked after the last statement; nothing can be sent once `running` is empty.
+    running.lock().expect("the main thread panicked").take();
+    while let Ok(request) = requests.try_recv() {
+        let result = match request["command"].as_str() {
+            Some("disconnect" | "terminate") => Ok(Value::Null),
+            Some("threads") => Ok(threads()),
+            _ => Err("The program has ended".to_string()),
+        };
+        let _ = client.respond(&request, result);
+    }
+
+    let _ = flush_output(&client, &output);
+    let exit_code = match result {
+        Ok(_) => 0,
+        Err(RuntimeError::Interrupted) => 1,
+        Err(err) => {
+            let line = line_offsets.line(interpreter.error_span().start);
+            let message = format!("Error: {:?} at line {}\n", err, line);
+            let _ = client.event("output", json!({ "category": "stderr", "output": message }));
+            1
+        }
+    };
+    let _ = client.event("exited", json!({ "exitCode": exit_code }));
+    let _ = client.event("terminated", json!({}));
+}
+
+/// Send what the program printed since the last time as an output event.
+fn flush_output(client: &Client, output: &Output) -> io::Result<()> {
+    let printed = output.take();
+    if printed.is_empty() {
+        return Ok(());
+    }
+    client.event("output", json!({ "category": "stdout", "output": printed }))
+}
+
+/// The debugger of a program run by the adapter, answering the client's requests.
+struct Adapter {
+    stepper: Stepper,
+    line_offsets: LineOffsets,
+    path: String,
+    requests: Rc<Receiver<Value>>,
+    client: Client,
+    output: Output,
+    /// The variables of the scopes handed out during the current pause,
+    /// each scope's `variablesReference` being its index plus one.
+    variables: Vec<Vec<Value>>,
+}
+
+impl Adapter {
+    /// Answer a request. Returns how to resume if the client asked to, and
+    /// fails to stop the program if the client disconnects.
+    fn request(&mut self, interpreter: &Interpreter, request: &Value) -> Result<Option<Resume>, RuntimeError> {
+        let arguments = &request["arguments"];
+        let (result, resume) = match request["command"].as_str().unwrap_or("") {
+            "setBreakpoints" => {
+                self.stepper.set_breakpoints(breakpoint_lines(arguments));
+                (Ok(breakpoints(self.stepper.breakpoints())), None)
+            }
+            "threads" => (Ok(threads()), None),
+            "stackTrace" => (Ok(self.stack_trace(interpreter)), None),
+            "scopes" => (self.scopes(interpreter, arguments), None),
+            "variables" => (self.variables(arguments), None),
+            "continue" => (Ok(json!({ "allThreadsContinued": true })), Some(Resume::Continue)),
+            "next" => (Ok(Value::Null), Some(Resume::StepOver)),
+            "stepIn" => (Ok(Value::Null), Some(Resume::StepIn)),
+            "stepOut" => (Ok(Value::Null), Some(Resume::StepOut)),
+            "disconnect" | "terminate" => {
+                let _ = self.client.respond(request, Ok(Value::Null));
+                return Err(RuntimeError::Interrupted);
+            }
+            command => (Err(format!("Unsupported request `{}`", command)), None),
+        };
+        let _ = self.client.respond(request, result);
+        Ok(resume)
+    }
+
+    fn stack_trace(&self, interpreter: &Interpreter) -> Value {
+        let frames: Vec<Value> = interpreter
+            .call_stack()
+            .iter()
+            .enumerate()
+            .map(|(id, frame)| {
+                json!({
+                    "id": id,
+                    "name": frame.function,
+                    "source": { "path": self.path },
+                    "line": self.line_offsets.line(frame.span.start),
+                    "column": self.line_offsets.column(frame.span.start),
+                })
+            })
+            .collect();
+        json!({ "totalFrames": frames.len(), "stackFrames": frames })
+    }
+
+    fn scopes(&mut self, interpreter: &Interpreter, arguments: &Value) -> Result<Value, String> {
+        let frame = arguments["frameId"].as_u64().map(|id| id as usize);
+        let call_stack = interpreter.call_stack();
+        let frame = frame.and_then(|id| call_stack.get(id)).ok_or("Unknown frame")?;
+
+        let mut scopes = Vec::new();
+        for (name, variables) in debug::scopes(&frame.env) {
+            let variables = variables
+                .iter()
+                .map(|(name, value)| {
+                    json!({
+                        "name": name,
+                        "value": debug::describe(value),
+                        "type": value.type_name(),
+                        "variablesReference": 0,
+                    })
+                })
+                .collect();
+            self.variables.push(variables);
+            scopes.push(json!({
+                "name": name,
+                "variablesReference": self.variables.len(),
+                "expensive": false,
+            }));
+        }
+        Ok(json!({ "scopes": scopes }))
+    }
+
+    fn variables(&self, arguments: &Value) -> Result<Value, String> {
+        let reference = arguments["variablesReference"].as_u64().unwrap_or(0) as usize;
+        let variables = reference.checked_sub(1).and_then(|i| self.variables.get(i));
+        // Values have no children, so anything but a scope has no variables.
+        Ok(json!({ "variables": variables.cloned().unwrap_or_default() }))
+    }
+}
+
+impl Debugger for Adapter {
+    fn before_stmt(&mut self, interpreter: &Interpreter, stmt: &WithSpan<Stmt>) -> Result<(), RuntimeError> {
+        let _ = flush_output(&self.client, &self.output);
+
+        // Requests sent while the program runs, such as new breakpoints.
+        loop {
+            match self.requests.try_recv() {
+                Ok(request) => {
+                    if let Some(resume) = self.request(interpreter, &request)? {
+                        self.stepper.resume(resume);
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Err(RuntimeError::Interrupted),
+            }
+        }
+
+        let line = self.line_offsets.line(stmt.span.start);
+        let Some(pause) = self.stepper.should_pause(&stmt.value, line, interpreter.call_depth()) else {
+            return Ok(());
+        };
+        let reason = match pause {
+            Pause::Entry => "entry",
+            Pause::Breakpoint => "breakpoint",
+            Pause::Step => "step",
+        };
+        let stopped = json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true });
+        let _ = self.client.event("stopped", stopped);
+
+        loop {
+            let request = self.requests.recv().map_err(|_| RuntimeError::Interrupted)?;
+            if let Some(resume) = self.request(interpreter, &request)? {
+                self.variables.clear();
+                self.stepper.resume(resume);
+                return Ok(());
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufReader, PipeReader, PipeWriter};
+    use std::path::PathBuf;
+
+    /// A scripted client talking to a server on another thread.
+    struct TestClient {
+        writer: PipeWriter,
+        reader: BufReader<PipeReader>,
+        seq: i64,
+        events: Vec<Value>,
+        server: Option<thread::JoinHandle<io::Result<()>>>,
+    }
+
+    impl TestClient {
+        fn start() -> Self {
+            let (server_input, writer) = io::pipe().unwrap();
+            let (reader, server_output) = io::pipe().unwrap();
+            let server = thread::spawn(move || serve(BufReader::new(server_input), server_output));
+            TestClient { writer, reader: BufReader::new(reader), seq: 0, events: vec![], server: Some(server) }
+        }
+
+        /// Send a request and wait for its response.
+        fn request(&mut self, command: &str, arguments: Value) -> Value {
+            self.seq += 1;
+            let request = json!({ "seq": self.seq, "type": "request", "command": command, "arguments": arguments });
+            write_message(&mut self.writer, &request).unwrap();
+            loop {
+                let message = read_message(&mut self.reader).unwrap().expect("a response");
+                if message["type"] == "response" && message["request_seq"] == self.seq {
+                    return message;
+                }
+                self.events.push(message);
+            }
+        }
+
+        fn event(&mut self, event: &str) -> Value {
+            if let Some(i) = self.events.iter().position(|e| e["event"] == event) {
+                return self.events.remove(i);
+            }
+            loop {
+                let message = read_message(&mut self.reader).unwrap().expect("an event");
+                if message["event"] == event {
+                    return message;
+                }
+                self.events.push(message);
+            }
+        }
+
+        /// The function and line of each frame while paused.
+        fn stack(&mut self) -> Vec<(String, u64)> {
+            let response = self.request("stackTrace", json!({ "threadId": THREAD_ID }));
+            let frames = response["body"]["stackFrames"].as_array().unwrap().clone();
+            frames
+                .iter()
+                .map(|f| (f["name"].as_str().unwrap().to_string(), f["line"].as_u64().unwrap()))
+                .collect()
+        }
+
+        fn launch(&mut self, path: &PathBuf, stop_on_entry: bool, breakpoints: &[usize]) {
+            assert_eq!(self.request("initialize", json!({ "adapterID": "rlux" }))["success"], true);
+            self.event("initialized");
+            let launch = self.request("launch", json!({ "program": path, "stopOnEntry": stop_on_entry }));
+            assert_eq!(launch["success"], true, "{}", launch);
+            let lines: Vec<Value> = breakpoints.iter().map(|line| json!({ "line": line })).collect();
+            let set = self.request("setBreakpoints", json!({ "source": { "path": path }, "breakpoints": lines }));
+            assert_eq!(set["body"]["breakpoints"].as_array().unwrap().len(), breakpoints.len());
+            self.request("configurationDone", json!({}));
+        }
+
+        fn finish(mut self) {
+            assert_eq!(self.request("disconnect", json!({}))["success"], true);
+            drop(self.writer);
+            self.server.take().unwrap().join().unwrap().unwrap();
+        }
+    }
+
+    fn script(name: &str, source: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("rlux-dap-{}-{}.lux", std::process::id(), name));
+        fs::write(&path, source).unwrap();
+        path
+    }
+
+    const SOURCE: &str = "\
+fun add(a, b) {
+  var sum = a + b;
+  return sum;
+}
+var x = add(1, 2);
+print x;
+";
+
+    #[test]
+    fn test_breakpoints_and_variables() {
+        let path = script("breakpoints", SOURCE);
+        let mut client = TestClient::start();
+        client.launch(&path, false, &[3]);
+
+        assert_eq!(client.event("stopped")["body"]["reason"], "breakpoint");
+        assert_eq!(client.stack(), vec![("add".to_string(), 3), ("<script>".to_string(), 5)]);
+
+        let scopes = client.request("scopes", json!({ "frameId": 0 }));
+        let scopes = scopes["body"]["scopes"].as_array().unwrap().clone();
+        let names: Vec<&str> = scopes.iter().map(|s| s["name"].as_str().unwrap()).collect();
+        assert_eq!(names, vec!["Locals", "Enclosing", "Globals"]);
+        let enclosing = client.request("variables", json!({ "variablesReference": scopes[1]["variablesReference"] }));
+        assert_eq!(
+            enclosing["body"]["variables"],
+            json!([
+                { "name": "a", "value": "1", "type": "number", "variablesReference": 0 },
+                { "name": "b", "value": "2", "type": "number", "variablesReference": 0 },
+            ])
+        );
+
+        client.request("next", json!({ "threadId": THREAD_ID }));
+        assert_eq!(client.event("stopped")["body"]["reason"], "step");
+        assert_eq!(client.stack(), vec![("<script>".to_string(), 6)]);
+
+        client.request("continue", json!({ "threadId": THREAD_ID }));
+        assert_eq!(client.event("output")["body"]["output"], "3\n");
+        assert_eq!(client.event("exited")["body"]["exitCode"], 0);
+        client.event("terminated");
+        client.finish();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_stepping_in_and_out() {
+        let path = script("stepping", SOURCE);
+        let mut client = TestClient::start();
+        client.launch(&path, true, &[]);
+
+        assert_eq!(client.event("stopped")["body"]["reason"], "entry");
+        assert_eq!(client.stack(), vec![("<script>".to_string(), 1)]);
+        client.request("next", json!({}));
+        client.event("stopped");
+        client.request("stepIn", json!({}));
+        client.event("stopped");
+        assert_eq!(client.stack(), vec![("add".to_string(), 2), ("<script>".to_string(), 5)]);
+        client.request("stepOut", json!({}));
+        client.event("stopped");
+        assert_eq!(client.stack(), vec![("<script>".to_string(), 6)]);
+
+        // Disconnecting stops the paused program.
+        client.finish();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_launch_checks_the_program() {
+        let path = script("invalid", "print 1");
+        let mut client = TestClient::start();
+        client.request("initialize", json!({}));
+        let launch = client.request("launch", json!({ "program": path }));
+        assert_eq!(launch["success"], false);
+        assert!(launch["message"].as_str().unwrap().contains("Expected ;"), "{}", launch);
+        client.finish();
+        fs::remove_file(path).unwrap();
+    }
+}
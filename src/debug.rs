@@ -0,0 +1,376 @@
+//! A step debugger for lux scripts.
+//!
+//! `Stepper` decides where a running program pauses: at line breakpoints and
+//! after stepping in, over or out of function calls. `Cli` is the interactive
+//! debugger behind `rlux debug`, reading commands from a prompt whenever the
+//! program pauses.
+
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+
+use crate::{
+    ast::Stmt,
+    interpreter::{Debugger, Environment, Interpreter, LuxValue, RuntimeError},
+    position::{LineOffsets, WithSpan},
+};
+
+/// How to go on after a pause.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// Run until the next breakpoint.
+    Continue,
+    /// Pause at the next statement, entering function calls.
+    StepIn,
+    /// Pause at the next statement of the same function, running calls to the end.
+    StepOver,
+    /// Pause once the current function has returned.
+    StepOut,
+}
+
+/// Why the program paused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pause {
+    Entry,
+    Breakpoint,
+    Step,
+}
+
+/// Decides before each statement whether the program pauses.
+///
+/// Only statements starting a new line pause at breakpoints, so a line with
+/// several statements is one stop.
+#[derive(Debug)]
+pub struct Stepper {
+    breakpoints: BTreeSet<usize>,
+    resume: Resume,
+    /// The line and call depth of the last pause.
+    origin: Option<(usize, usize)>,
+    previous_line: Option<usize>,
+}
+
+impl Stepper {
+    /// A stepper that pauses at the first statement if `stop_on_entry`, and
+    /// otherwise at the first breakpoint.
+    pub fn new(stop_on_entry: bool) -> Self {
+        Stepper {
+            breakpoints: BTreeSet::new(),
+            resume: if stop_on_entry { Resume::StepIn } else { Resume::Continue },
+            origin: None,
+            previous_line: None,
+        }
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn add_breakpoint(&mut self, line: usize) {
+        self.breakpoints.insert(line);
+    }
+
+    /// Returns false if there was no breakpoint on the line.
+    pub fn remove_breakpoint(&mut self, line: usize) -> bool {
+        self.breakpoints.remove(&line)
+    }
+
+    pub fn set_breakpoints(&mut self, lines: impl IntoIterator<Item = usize>) {
+        self.breakpoints = lines.into_iter().collect();
+    }
+
+    pub fn resume(&mut self, resume: Resume) {
+        self.resume = resume;
+    }
+
+    /// Whether to pause before a statement on `line`, with `depth` functions being called.
+    pub fn should_pause(&mut self, stmt: &Stmt, line: usize, depth: usize) -> Option<Pause> {
+        // A block is not a step of its own, its first statement is.
+        if matches!(stmt, Stmt::Block(_)) {
+            return None;
+        }
+        let new_line = self.previous_line != Some(line);
+        self.previous_line = Some(line);
+
+        let at_breakpoint = new_line && self.breakpoints.contains(&line);
+        let stepped = match (self.resume, self.origin) {
+            (Resume::Continue, _) => false,
+            (_, None) => true,
+            (Resume::StepIn, Some(origin)) => origin != (line, depth),
+            (Resume::StepOver, Some((origin_line, origin_depth))) => {
+                depth < origin_depth || (depth == origin_depth && line != origin_line)
+            }
+            (Resume::StepOut, Some((_, origin_depth))) => depth < origin_depth,
+        };
+
+        let pause = match (at_breakpoint, stepped) {
+            (true, _) => Pause::Breakpoint,
+            (false, true) if self.origin.is_none() => Pause::Entry,
+            (false, true) => Pause::Step,
+            (false, false) => return None,
+        };
+        self.origin = Some((line, depth));
+        Some(pause)
+    }
+}
+
+/// The named scopes visible from `env`, innermost first: `Locals`, the
+/// `Enclosing` scopes that define anything, and `Globals` without the
+/// functions the interpreter provides.
+pub fn scopes(env: &Environment) -> Vec<(&'static str, Vec<(String, LuxValue)>)> {
+    let mut scopes = Vec::new();
+    let mut current = Some(env.clone());
+    while let Some(env) = current {
+        current = env.pop();
+        let mut variables = env.variables();
+        let name = if current.is_none() {
+            variables.retain(|(_, value)| !matches!(value, LuxValue::Callable(f) if f.is_native()));
+            "Globals"
+        } else if scopes.is_empty() {
+            "Locals"
+        } else if variables.is_empty() {
+            continue;
+        } else {
+            "Enclosing"
+        };
+        scopes.push((name, variables));
+    }
+    scopes
+}
+
+/// A value as the debugger shows it, quoting strings.
+pub fn describe(value: &LuxValue) -> String {
+    match value {
+        LuxValue::String(s) => format!("{:?}", s),
+        value => value.to_string(),
+    }
+}
+
+const HELP: &str = "\
+break, b <line>     pause whenever the line is reached (without a line: list breakpoints)
+delete, d <line>    remove the breakpoint on the line
+continue, c         run until the next breakpoint
+step, s             run to the next statement, entering calls
+next, n             run to the next statement in this function
+finish, out         run until this function returns
+locals, l           show the variables in scope
+print, p <name>     show a variable
+backtrace, bt       show the calls being run
+quit, q             stop the program";
+
+/// The interactive debugger: prints where the program paused and reads
+/// commands from `input` until told to go on.
+pub struct Cli<R, W> {
+    stepper: Stepper,
+    source: String,
+    line_offsets: LineOffsets,
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> Cli<R, W> {
+    /// A debugger for `source` that pauses at its first statement.
+    pub fn new(source: &str, input: R, output: W) -> Self {
+        Cli {
+            stepper: Stepper::new(true),
+            source: source.to_string(),
+            line_offsets: LineOffsets::new(source),
+            input,
+            output,
+        }
+    }
+
+    fn source_line(&self, line: usize) -> &str {
+        self.source.lines().nth(line.saturating_sub(1)).unwrap_or("").trim()
+    }
+
+    /// Run one command. Returns how to resume, or `None` to stay paused.
+    fn command(&mut self, interpreter: &Interpreter, line: &str) -> std::io::Result<Option<Resume>> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("");
+        let argument = words.next();
+
+        match (command, argument) {
+            ("" | "help" | "h", _) => writeln!(self.output, "{}", HELP)?,
+            ("continue" | "c", _) => return Ok(Some(Resume::Continue)),
+            ("step" | "s", _) => return Ok(Some(Resume::StepIn)),
+            ("next" | "n", _) => return Ok(Some(Resume::StepOver)),
+            ("finish" | "out", _) => return Ok(Some(Resume::StepOut)),
+            ("break" | "b", None) => {
+                let lines: Vec<String> = self.stepper.breakpoints().map(|l| l.to_string()).collect();
+                writeln!(self.output, "breakpoints: {}", lines.join(", "))?;
+            }
+            ("break" | "b", Some(line)) => match line.parse() {
+                Ok(line) => {
+                    self.stepper.add_breakpoint(line);
+                    writeln!(self.output, "breakpoint at line {}", line)?;
+                }
+                Err(_) => writeln!(self.output, "not a line number: {}", line)?,
+            },
+            ("delete" | "d", Some(line)) => match line.parse() {
+                Ok(line) if self.stepper.remove_breakpoint(line) => {
+                    writeln!(self.output, "removed breakpoint at line {}", line)?
+                }
+                _ => writeln!(self.output, "no breakpoint at line {}", line)?,
+            },
+            ("locals" | "l", _) => {
+                for (name, variables) in scopes(interpreter.environment()) {
+                    writeln!(self.output, "{}:", name.to_lowercase())?;
+                    for (name, value) in &variables {
+                        writeln!(self.output, "  {} = {}", name, describe(value))?;
+                    }
+                }
+            }
+            ("print" | "p", Some(name)) => match interpreter.environment().get(name) {
+                Some(value) => writeln!(self.output, "{} = {}", name, describe(&value))?,
+                None => writeln!(self.output, "{} is not defined", name)?,
+            },
+            ("backtrace" | "bt", _) => {
+                for (i, frame) in interpreter.call_stack().iter().enumerate() {
+                    let line = self.line_offsets.line(frame.span.start);
+                    writeln!(self.output, "#{} {} at line {}", i, frame.function, line)?;
+                }
+            }
+            _ => writeln!(self.output, "unknown command `{}`, try `help`", line.trim())?,
+        }
+        Ok(None)
+    }
+
+    fn pause(&mut self, interpreter: &Interpreter, line: usize) -> std::io::Result<Option<Resume>> {
+        let text = self.source_line(line).to_string();
+        writeln!(self.output, "{}: {}", line, text)?;
+        loop {
+            write!(self.output, "(debug) ")?;
+            self.output.flush()?;
+            let mut command = String::new();
+            if self.input.read_line(&mut command)? == 0 {
+                return Ok(None);
+            }
+            if matches!(command.trim(), "quit" | "q") {
+                return Ok(None);
+            }
+            if let Some(resume) = self.command(interpreter, &command)? {
+                return Ok(Some(resume));
+            }
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> Debugger for Cli<R, W> {
+    fn before_stmt(&mut self, interpreter: &Interpreter, stmt: &WithSpan<Stmt>) -> Result<(), RuntimeError> {
+        let line = self.line_offsets.line(stmt.span.start);
+        if self.stepper.should_pause(&stmt.value, line, interpreter.call_depth()).is_none() {
+            return Ok(());
+        }
+        match self.pause(interpreter, line) {
+            Ok(Some(resume)) => {
+                self.stepper.resume(resume);
+                Ok(())
+            }
+            // Quitting and the end of the input stop the program, as does a broken terminal.
+            Ok(None) => Err(RuntimeError::Quit),
+            Err(_) => Err(RuntimeError::Interrupted),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{interpreter::Output, program::Program, resolver::Resolver, scanner::Scanner};
+    use std::cell::RefCell;
+    use std::io;
+    use std::rc::Rc;
+
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Debug `source` with the commands, returning what the debugger and the program printed.
+    fn debug(source: &str, commands: &str) -> (String, String) {
+        let transcript = Shared::default();
+        let output = Output::captured();
+        let mut interpreter = Interpreter::builder().output(output.clone()).build();
+        let cli = Cli::new(source, io::Cursor::new(commands.to_string()), transcript.clone());
+        interpreter.set_debugger(Some(Box::new(cli)));
+        crate::run(source, &mut interpreter);
+        let transcript = String::from_utf8(transcript.0.borrow().clone()).unwrap();
+        (transcript.replace("(debug) ", ""), output.take())
+    }
+
+    const SOURCE: &str = "\
+fun add(a, b) {
+  var sum = a + b;
+  return sum;
+}
+var x = add(1, 2);
+print x;
+";
+
+    #[test]
+    fn test_breakpoints_and_inspection() {
+        let (transcript, printed) = debug(SOURCE, "b 3\nc\nl\np a\nbt\nc\n");
+        assert_eq!(
+            transcript,
+            "\
+1: fun add(a, b) {
+breakpoint at line 3
+3: return sum;
+locals:
+  sum = 3
+enclosing:
+  a = 1
+  b = 2
+globals:
+  add = <fun add>
+a = 1
+#0 add at line 3
+#1 <script> at line 5
+"
+        );
+        assert_eq!(printed, "3\n");
+    }
+
+    #[test]
+    fn test_stepping() {
+        let (transcript, _) = debug(SOURCE, "n\ns\ns\nout\nn\nn\n");
+        let stops: Vec<&str> = transcript.lines().collect();
+        assert_eq!(stops, vec![
+            "1: fun add(a, b) {",
+            "5: var x = add(1, 2);",
+            "2: var sum = a + b;",
+            "3: return sum;",
+            "6: print x;",
+        ]);
+
+        let (transcript, _) = debug(SOURCE, "n\nn\nn\n");
+        let stops: Vec<&str> = transcript.lines().collect();
+        assert_eq!(stops, vec!["1: fun add(a, b) {", "5: var x = add(1, 2);", "6: print x;"]);
+    }
+
+    #[test]
+    fn test_quit_stops_the_program() {
+        let (transcript, printed) = debug(SOURCE, "n\nq\n");
+        assert_eq!(transcript, "1: fun add(a, b) {\n5: var x = add(1, 2);\n");
+        assert_eq!(printed, "");
+    }
+
+    #[test]
+    fn test_quit_and_end_of_input_are_not_errors() {
+        for commands in ["q\n", ""] {
+            let mut interpreter = Interpreter::builder().output(Output::captured()).build();
+            interpreter.set_debugger(Some(Box::new(Cli::new(SOURCE, io::Cursor::new(commands), Vec::new()))));
+            let program = Program::parse(&Scanner::new(SOURCE).run()).unwrap();
+            Resolver::new().run(&program).unwrap();
+            assert!(matches!(interpreter.run(&program), Err(RuntimeError::Quit)));
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! Dumps of the tokens and the syntax tree of lux source, for debugging the
+//! language itself and for external tools.
+
+use serde_json::{json, Value};
+
+use crate::{
+    ast::{Expr, Stmt},
+    position::{BytePos, LineOffsets, Span, WithSpan},
+    program::Program,
+    token::Token,
+};
+
+/// One line per token: `line:column kind`.
+pub fn tokens(source: &str, tokens: &[WithSpan<Token>]) -> Vec<String> {
+    let line_offsets = LineOffsets::new(source);
+    tokens
+        .iter()
+        .map(|token| {
+            format!(
+                "{}:{} {:?}",
+                line_offsets.line(token.span.start),
+                line_offsets.column(token.span.start),
+                token.value
+            )
+        })
+        .collect()
+}
+
+/// The program as an indented tree, one node per line.
+///
+/// ```
+/// use rlux::{dump, program::Program, scanner::Scanner};
+/// let source = "print 1 + 2;";
+/// let program = Program::parse(&Scanner::new(source).run()).unwrap();
+/// assert_eq!(
+///     dump::tree(source, &program),
+///     "Program\n  Print 1:1-1:13\n    Binary +\n      Number 1\n      Number 2\n"
+/// );
+/// ```
+pub fn tree(source: &str, program: &Program) -> String {
+    let line_offsets = LineOffsets::new(source);
+    let mut out = String::new();
+    program_node(program).write_tree(&line_offsets, 0, &mut out);
+    out
+}
+
+/// The program as JSON, each node being `{"kind", "detail"?, "span"?, "children"}`.
+pub fn json(source: &str, program: &Program) -> Value {
+    let line_offsets = LineOffsets::new(source);
+    program_node(program).to_json(&line_offsets)
+}
+
+/// A node of the dumped tree. Only statements and names have spans in the AST.
+struct Node {
+    kind: &'static str,
+    detail: Option<String>,
+    span: Option<Span>,
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn new(kind: &'static str) -> Self {
+        Node { kind, detail: None, span: None, children: vec![] }
+    }
+
+    fn detail(mut self, detail: impl ToString) -> Self {
+        self.detail = Some(detail.to_string());
+        self
+    }
+
+    fn children(mut self, children: Vec<Node>) -> Self {
+        self.children = children;
+        self
+    }
+
+    fn write_tree(&self, line_offsets: &LineOffsets, depth: usize, out: &mut String) {
+        out.push_str(&"  ".repeat(depth));
+        out.push_str(self.kind);
+        if let Some(detail) = &self.detail {
+            out.push(' ');
+            out.push_str(detail);
+        }
+        if let Some(span) = self.span {
+            let (start, end) = (position(line_offsets, span.start), position(line_offsets, span.end));
+            out.push_str(&format!(" {}:{}-{}:{}", start.0, start.1, end.0, end.1));
+        }
+        out.push('\n');
+        for child in &self.children {
+            child.write_tree(line_offsets, depth + 1, out);
+        }
+    }
+
+    fn to_json(&self, line_offsets: &LineOffsets) -> Value {
+        let mut node = json!({ "kind": self.kind });
+        if let Some(detail) = &self.detail {
+            node["detail"] = json!(detail);
+        }
+        if let Some(span) = self.span {
+            let location = |pos: BytePos| {
+                let (line, column) = position(line_offsets, pos);
+                json!({ "offset": pos.0, "line": line, "column": column })
+            };
+            node["span"] = json!({ "start": location(span.start), "end": location(span.end) });
+        }
+        node["children"] = self.children.iter().map(|c| c.to_json(line_offsets)).collect();
+        node
+    }
+}
+
+fn position(line_offsets: &LineOffsets, pos: BytePos) -> (usize, usize) {
+    (line_offsets.line(pos), line_offsets.column(pos))
+}
+
+fn program_node(program: &Program) -> Node {
+    Node::new("Program").children(program.statements.iter().map(stmt_node).collect())
+}
+
+fn stmt_node(stmt: &WithSpan<Stmt>) -> Node {
+    let node = match &stmt.value {
+        Stmt::Expression(e) => Node::new("Expression").children(vec![expr_node(e)]),
+        Stmt::Print(e) => Node::new("Print").children(vec![expr_node(e)]),
+        Stmt::If(cond, then, else_) => {
+            let mut children = vec![expr_node(cond), stmt_node(then)];
+            children.extend(else_.as_deref().map(stmt_node));
+            Node::new("If").children(children)
+        }
+        Stmt::While(cond, body) => Node::new("While").children(vec![expr_node(cond), stmt_node(body)]),
+        Stmt::For(init, cond, incr, body) => Node::new("For").children(vec![
+            init.as_deref().map(stmt_node).unwrap_or(Node::new("Empty")),
+            cond.as_ref().map(expr_node).unwrap_or(Node::new("Empty")),
+            incr.as_ref().map(expr_node).unwrap_or(Node::new("Empty")),
+            stmt_node(body),
+        ]),
+        Stmt::Var(name, e) => Node::new("Var").detail(&name.value).children(vec![expr_node(e)]),
+        Stmt::Block(stmts) => Node::new("Block").children(stmts.iter().map(stmt_node).collect()),
+        Stmt::Return(e) => Node::new("Return").children(vec![expr_node(e)]),
+        Stmt::Function(name, params, body) => Node::new("Function")
+            .detail(format!(
+                "{}({})",
+                name.value,
+                params.iter().map(|p| &*p.value).collect::<Vec<_>>().join(", ")
+            ))
+            .children(vec![stmt_node(body)]),
+    };
+    Node { span: Some(stmt.span), ..node }
+}
+
+fn expr_node(expr: &Expr) -> Node {
+    match expr {
+        Expr::Number(n) => Node::new("Number").detail(n),
+        Expr::String(s) => Node::new("String").detail(format!("{:?}", s)),
+        Expr::True => Node::new("True"),
+        Expr::False => Node::new("False"),
+        Expr::Nil => Node::new("Nil"),
+        Expr::Grouping(e) => Node::new("Grouping").children(vec![expr_node(e)]),
+        Expr::LogicalOr(l, _, r) => Node::new("LogicalOr").children(vec![expr_node(l), expr_node(r)]),
+        Expr::LogicalAnd(l, _, r) => Node::new("LogicalAnd").children(vec![expr_node(l), expr_node(r)]),
+        Expr::Unary(op, e) => Node::new("Unary").detail(op.print()).children(vec![expr_node(e)]),
+        Expr::Binary(l, op, r) => Node::new("Binary")
+            .detail(op.print())
+            .children(vec![expr_node(l), expr_node(r)]),
+        Expr::Variable(name, _) => Node {
+            span: Some(name.span),
+            ..Node::new("Variable").detail(&name.value)
+        },
+        Expr::Assignment(name, _, e) => Node {
+            span: Some(name.span),
+            ..Node::new("Assignment").detail(&name.value).children(vec![expr_node(e)])
+        },
+        Expr::Call(callee, args) => {
+            Node::new("Call").children(std::iter::once(callee.as_ref()).chain(args).map(expr_node).collect())
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::Scanner;
+
+    fn parse(source: &str) -> Program {
+        Program::parse(&Scanner::new(source).run()).unwrap()
+    }
+
+    #[test]
+    fn test_tokens_have_line_and_column() {
+        let source = "var a;\n  a = \"b\";";
+        let lines = tokens(source, &Scanner::new(source).run());
+        assert_eq!(lines[0], "1:1 Var");
+        assert_eq!(lines[3], "2:3 Identifier(\"a\")");
+        assert_eq!(lines[5], "2:7 String(\"b\")");
+    }
+
+    #[test]
+    fn test_tree_nests_statements() {
+        let source = "fun f(a) {\n  return a;\n}\nf(1);";
+        let expected = "\
+Program
+  Function f(a) 1:1-3:2
+    Block 1:10-3:2
+      Return 2:3-2:12
+        Variable a 2:10-2:11
+  Expression 4:1-4:6
+    Call
+      Variable f 4:1-4:2
+      Number 1
+";
+        assert_eq!(tree(source, &parse(source)), expected);
+    }
+
+    #[test]
+    fn test_json_includes_spans() {
+        let source = "var a = 1;";
+        let json = json(source, &parse(source));
+        let var = &json["children"][0];
+        assert_eq!(var["kind"], "Var");
+        assert_eq!(var["detail"], "a");
+        assert_eq!(var["span"]["end"], json!({ "offset": 10, "line": 1, "column": 11 }));
+        assert_eq!(var["children"][0]["kind"], "Number");
+        assert!(var["children"][0].get("span").is_none());
+    }
+}
@@ -1,6 +1,7 @@
 use crate::{
     ast::expr::{BinaryOp, Expr, UnaryOp},
     parser::Parser,
+    position::WithSpan,
     token::{Token, TokenKind},
 };
 
@@ -36,7 +37,7 @@ fn assignment(p: &mut Parser) -> Option<Expr> {
         let previous = p.previous();
         let value = assignment(p)?;
         match &expr {
-            Expr::Variable(name) => return Some(Expr::assignment(name.clone(), value)),
+            Expr::Variable(name, _) => return Some(Expr::assignment(name.clone(), value)),
             _ => {
                 p.error("Invalid assignment target", previous.span);
                 return None;
@@ -49,8 +50,9 @@ fn assignment(p: &mut Parser) -> Option<Expr> {
 fn logical_or(p: &mut Parser) -> Option<Expr> {
     let mut expr = logical_and(p)?;
     while p.is(TokenKind::Or) {
+        let operator = p.previous().span;
         let right = logical_and(p)?;
-        expr = Expr::logical_or(expr, right);
+        expr = Expr::logical_or(expr, operator, right);
     }
     Some(expr)
 }
@@ -58,8 +60,9 @@ fn logical_or(p: &mut Parser) -> Option<Expr> {
 fn logical_and(p: &mut Parser) -> Option<Expr> {
     let mut expr = equality(p)?;
     while p.is(TokenKind::And) {
+        let operator = p.previous().span;
         let right = equality(p)?;
-        expr = Expr::logical_and(expr, right);
+        expr = Expr::logical_and(expr, operator, right);
     }
     Some(expr)
 }
@@ -202,9 +205,10 @@ fn primary(p: &mut Parser) -> Option<Expr> {
         return Some(Expr::number(n));
     }
 
-    if let Token::String(s) = p.peek_token().value.clone() {
+    if let Token::String(s) = &p.peek_token().value {
+        let expr = Expr::String(p.intern(s));
         p.advance();
-        return Some(Expr::string(s));
+        return Some(expr);
     }
 
     if p.is(TokenKind::LeftParen) {
@@ -217,7 +221,7 @@ fn primary(p: &mut Parser) -> Option<Expr> {
     if p.is(TokenKind::Identifier) {
         let token = p.previous();
         match &token.value {
-            Token::Identifier(name) => return Some(Expr::variable(name.clone())),
+            Token::Identifier(name) => return Some(Expr::variable(WithSpan::new(name.clone(), token.span))),
             _ => panic!("Expected identifier"),
         }
     }
@@ -0,0 +1,311 @@
+//! Pretty printer that turns lux source into canonically formatted lux source.
+//!
+//! The formatter works on the AST, so the layout of the input does not matter,
+//! except for comments and blank lines which are kept. Statements are indented
+//! with two spaces, and at most one blank line is kept between statements.
+
+use crate::{
+    ast::{Expr, Stmt},
+    position::{BytePos, Diagnostic, LineOffsets, WithSpan},
+    program::Program,
+    scanner::Scanner,
+};
+use std::mem;
+
+const INDENT: &str = "  ";
+
+/// Format lux source code, failing if it does not parse.
+///
+/// ```
+/// let formatted = rlux::formatter::format("fun f(a){return a+1;} // add one").unwrap();
+/// assert_eq!(formatted, "fun f(a) {\n  return a + 1;\n} // add one\n");
+/// ```
+pub fn format(source: &str) -> Result<String, Vec<Diagnostic>> {
+    let mut scanner = Scanner::new(source);
+    let tokens = scanner.run();
+    let program = Program::parse(&tokens)?;
+
+    let mut formatter = Formatter {
+        lines: LineOffsets::new(source),
+        comments: scanner.comments(),
+        next_comment: 0,
+        out: String::new(),
+        indent: 0,
+        last_line: None,
+        continue_line: false,
+        block_end: BytePos(usize::MAX),
+    };
+    for stmt in &program.statements {
+        formatter.stmt(stmt, "");
+    }
+    formatter.comments_before(BytePos(usize::MAX));
+    Ok(formatter.out)
+}
+
+struct Formatter<'a> {
+    lines: LineOffsets,
+    comments: &'a [WithSpan<String>],
+    next_comment: usize,
+    out: String,
+    indent: usize,
+    /// Source line where the last statement or comment ended, used to keep blank lines.
+    last_line: Option<usize>,
+    /// Append the next line to the previous one, as in `} else {`.
+    continue_line: bool,
+    /// End of the innermost block, comments after it belong to the enclosing statement.
+    block_end: BytePos,
+}
+
+impl<'a> Formatter<'a> {
+    fn line(&mut self, text: &str) {
+        if self.continue_line {
+            self.continue_line = false;
+            self.out.pop();
+        } else {
+            for _ in 0..self.indent {
+                self.out.push_str(INDENT);
+            }
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn blank_line_before(&mut self, pos: BytePos) {
+        let line = self.lines.line(pos);
+        if let Some(last_line) = self.last_line {
+            if line > last_line + 1 {
+                self.out.push('\n');
+            }
+        }
+        self.last_line = Some(line);
+    }
+
+    fn pending_comment(&self) -> Option<&'a WithSpan<String>> {
+        self.comments.get(self.next_comment)
+    }
+
+    /// Write all comments that start before `pos` on their own lines.
+    fn comments_before(&mut self, pos: BytePos) {
+        while let Some(comment) = self.pending_comment().filter(|c| c.span.start < pos) {
+            self.next_comment += 1;
+            self.continue_line = false;
+            self.blank_line_before(comment.span.start);
+            self.line(&comment.value);
+        }
+    }
+
+    /// Append a comment that follows `pos` on the same source line to the last line.
+    fn trailing_comment(&mut self, pos: BytePos) {
+        let line = self.lines.line(pos);
+        if let Some(comment) = self
+            .pending_comment()
+            .filter(|c| c.span.start >= pos && c.span.start < self.block_end)
+            .filter(|c| self.lines.line(c.span.start) == line)
+        {
+            self.next_comment += 1;
+            self.out.pop();
+            self.out.push(' ');
+            self.out.push_str(&comment.value);
+            self.out.push('\n');
+        }
+    }
+
+    /// Write a statement, starting its first line with `prefix`.
+    fn stmt(&mut self, stmt: &WithSpan<Stmt>, prefix: &str) {
+        if prefix.is_empty() && !self.continue_line {
+            self.comments_before(stmt.span.start);
+            self.blank_line_before(stmt.span.start);
+        }
+
+        match &stmt.value {
+            Stmt::Block(stmts) => self.block(prefix, stmts, stmt.span.end),
+            Stmt::Function(name, params, body) => {
+                let params: Vec<&str> = params.iter().map(|p| &*p.value).collect();
+                let header = format!("{}fun {}({}) ", prefix, name.value, params.join(", "));
+                self.body(&header, body);
+            }
+            Stmt::If(cond, then, else_) => {
+                let header = format!("{}if ({}) ", prefix, expr(cond));
+                match else_ {
+                    Some(else_) => {
+                        if let Stmt::Block(stmts) = &then.value {
+                            // Comments between `}` and `else` move into the block.
+                            self.block(&header, stmts, else_.span.start);
+                            self.continue_line = true;
+                            self.stmt(else_, " else ");
+                        } else {
+                            self.stmt(then, &header);
+                            self.stmt(else_, "else ");
+                        }
+                    }
+                    None => self.body(&header, then),
+                }
+            }
+            Stmt::While(cond, body) => {
+                let header = format!("{}while ({}) ", prefix, expr(cond));
+                self.body(&header, body);
+            }
+            Stmt::For(init, cond, incr, body) => {
+                let init = init.as_ref().map(|i| simple(&i.value)).unwrap_or(";".to_string());
+                let cond = cond.as_ref().map(|c| format!(" {}", expr(c))).unwrap_or_default();
+                let incr = incr.as_ref().map(|i| format!(" {}", expr(i))).unwrap_or_default();
+                let header = format!("{}for ({}{};{}) ", prefix, init, cond, incr);
+                self.body(&header, body);
+            }
+            simple_stmt => {
+                // Comments inside a statement can not stay there, put them in front of it.
+                if !self.continue_line {
+                    self.comments_before(stmt.span.end);
+                }
+                self.line(&format!("{}{}", prefix, simple(simple_stmt)));
+            }
+        }
+
+        self.trailing_comment(stmt.span.end);
+        self.last_line = Some(self.lines.line(stmt.span.end));
+    }
+
+    /// Write the body of a compound statement after its header.
+    fn body(&mut self, header: &str, body: &WithSpan<Stmt>) {
+        match &body.value {
+            Stmt::Block(stmts) => self.block(header, stmts, body.span.end),
+            _ => self.stmt(body, header),
+        }
+    }
+
+    /// Write a block, keeping the comments inside it that start before `end`.
+    fn block(&mut self, prefix: &str, stmts: &[WithSpan<Stmt>], end: BytePos) {
+        let has_comments = self.pending_comment().is_some_and(|c| c.span.start < end);
+        if stmts.is_empty() && !has_comments {
+            self.line(&format!("{}{{}}", prefix));
+            return;
+        }
+
+        self.line(&format!("{}{{", prefix));
+        self.indent += 1;
+        self.last_line = None;
+        let outer_end = mem::replace(&mut self.block_end, end);
+        for stmt in stmts {
+            self.stmt(stmt, "");
+        }
+        self.comments_before(end);
+        self.block_end = outer_end;
+        self.indent -= 1;
+        self.line("}");
+    }
+}
+
+/// A statement that fits on one line.
+fn simple(stmt: &Stmt) -> String {
+    match stmt {
+        Stmt::Expression(e) => format!("{};", expr(e)),
+        Stmt::Print(e) => format!("print {};", expr(e)),
+        Stmt::Var(name, Expr::Nil) => format!("var {};", name.value),
+        Stmt::Var(name, e) => format!("var {} = {};", name.value, expr(e)),
+        Stmt::Return(Expr::Nil) => "return;".to_string(),
+        Stmt::Return(e) => format!("return {};", expr(e)),
+        other => panic!("Not a simple statement: {:?}", other),
+    }
+}
+
+fn expr(expr: &Expr) -> String {
+    match expr {
+        Expr::Number(n) => {
+            if n.fract() == 0.0 && n.abs() < 1e15 {
+                format!("{:.0}", n)
+            } else {
+                n.to_string()
+            }
+        }
+        Expr::String(s) => format!("\"{}\"", s),
+        Expr::True => "true".to_string(),
+        Expr::False => "false".to_string(),
+        Expr::Nil => "nil".to_string(),
+        Expr::Grouping(e) => format!("({})", self::expr(e)),
+        Expr::LogicalOr(l, _, r) => format!("{} or {}", self::expr(l), self::expr(r)),
+        Expr::LogicalAnd(l, _, r) => format!("{} and {}", self::expr(l), self::expr(r)),
+        Expr::Unary(op, e) => format!("{}{}", op.print(), self::expr(e)),
+        Expr::Binary(l, op, r) => format!("{} {} {}", self::expr(l), op.print(), self::expr(r)),
+        Expr::Variable(name, _) => name.value.clone(),
+        Expr::Assignment(name, _, e) => format!("{} = {}", name.value, self::expr(e)),
+        Expr::Call(callee, args) => format!(
+            "{}({})",
+            self::expr(callee),
+            args.iter().map(self::expr).collect::<Vec<_>>().join(", ")
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::StructuralPrinter;
+    use std::fs;
+
+    fn structure(source: &str) -> Vec<String> {
+        let tokens = Scanner::new(source).run();
+        let program = Program::parse(&tokens).unwrap();
+        program.statements.iter().map(|s| s.print_structural()).collect()
+    }
+
+    #[test]
+    fn test_indents_blocks() {
+        let source = "fun count(n){if(n>1)count(n-1);print n;}";
+        let expected = "fun count(n) {\n  if (n > 1) count(n - 1);\n  print n;\n}\n";
+        assert_eq!(format(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_if_else_chains() {
+        let source = "if (a) { print 1; } else if (b) print 2; else { print 3; }";
+        let expected = "if (a) {\n  print 1;\n} else if (b) print 2;\nelse {\n  print 3;\n}\n";
+        assert_eq!(format(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_for_loops() {
+        let expected = "for (var i = 0; i < 3; i = i + 1) print i;\n";
+        assert_eq!(format("for(var i=0;i<3;i=i+1)print i;").unwrap(), expected);
+        assert_eq!(format("for(;;){}").unwrap(), "for (;;) {}\n");
+    }
+
+    #[test]
+    fn test_keeps_comments_and_single_blank_lines() {
+        let source = "// header\n\n\n\nvar a = 1; // one\n{\n  // inside\n}\nprint a;\n// end\n";
+        let expected = "// header\n\nvar a = 1; // one\n{\n  // inside\n}\nprint a;\n// end\n";
+        assert_eq!(format(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_keeps_grouping() {
+        assert_eq!(format("print (1+2)*-3;").unwrap(), "print (1 + 2) * -3;\n");
+    }
+
+    #[test]
+    fn test_scripts_are_preserved_and_idempotent() {
+        // Scripts that test syntax errors, which the formatter must refuse.
+        const INVALID: &[&str] = &["missing_colon.lux"];
+
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/scripts");
+        let scripts = crate::test_runner::scripts(&[dir]).unwrap();
+        assert!(!scripts.is_empty());
+        for path in scripts {
+            let source = fs::read_to_string(&path).unwrap();
+            let name = path.file_name().unwrap().to_str().unwrap();
+            if INVALID.contains(&name) {
+                assert!(format(&source).is_err(), "{} should not format", path.display());
+                continue;
+            }
+            let formatted = format(&source).unwrap_or_else(|err| panic!("{} failed to format: {:?}", path.display(), err));
+
+            assert_eq!(structure(&formatted), structure(&source), "{} changed meaning", path.display());
+            assert_eq!(format(&formatted).unwrap(), formatted, "{} is not idempotent", path.display());
+            let comments = |s: &str| {
+                let mut scanner = Scanner::new(s);
+                scanner.run();
+                scanner.comments().iter().map(|c| c.value.clone()).collect::<Vec<_>>()
+            };
+            assert_eq!(comments(&formatted), comments(&source), "{} lost comments", path.display());
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! Shared copies of identifiers and string literals.
+//!
+//! Interning the same text twice returns the same `Rc<str>`, so copying it is
+//! a reference count increment and comparing two interned strings usually
+//! stops at comparing their pointers.
+//!
+//! Every parse has its own interner, so the strings of a program are freed
+//! with it rather than living as long as the process.
+
+use std::{collections::HashSet, rc::Rc};
+
+#[derive(Debug, Default)]
+pub struct Interner {
+    strings: HashSet<Rc<str>>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The shared copy of `s`.
+    pub fn intern(&mut self, s: &str) -> Rc<str> {
+        match self.strings.get(s) {
+            Some(interned) => interned.clone(),
+            None => {
+                let interned: Rc<str> = Rc::from(s);
+                self.strings.insert(interned.clone());
+                interned
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_text_is_shared() {
+        let mut strings = Interner::new();
+        let a = strings.intern("hello");
+        let b = strings.intern(&"hello".to_string());
+        assert!(Rc::ptr_eq(&a, &b));
+        assert!(!Rc::ptr_eq(&a, &strings.intern("world")));
+        assert_eq!(&*a, "hello");
+    }
+}
@@ -2,60 +2,184 @@ pub mod value;
 pub mod run_time_error;
 pub mod environment;
 pub mod lib;
+pub mod memory;
+pub mod builder;
+pub mod snapshot;
+pub mod json;
+pub mod output;
+pub mod debugger;
+pub mod profiler;
+pub mod coverage;
 
-use std::collections::HashMap;
 use std::mem;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 pub use value::LuxValue;
 pub use value::LuxCallable;
 pub use run_time_error::RuntimeError;
 pub use environment::Environment;
+pub use memory::Memory;
+pub use builder::InterpreterBuilder;
+pub use snapshot::Snapshot;
+pub use output::Output;
+pub use debugger::{Debugger, Frame};
+pub use profiler::{Profile, Profiler};
+pub use coverage::Coverage;
 
 use crate::ast::*;
+use crate::position::{Span, WithSpan};
 use crate::program::Program;
 
 #[derive(Debug)]
 pub struct Interpreter {
     globals: Environment,
     env: Environment,
-    locals: HashMap<String, usize>,
+    /// Remaining number of steps (statements and calls) the interpreter may take.
+    /// `None` means there is no limit.
+    fuel: Option<u64>,
+    /// Set by the host to stop a running program at the next step.
+    interrupt: Arc<AtomicBool>,
+    memory: Rc<Memory>,
+    output: Output,
+    /// The statement being run. After a runtime error, the statement that failed.
+    span: Span,
+    calls: Vec<debugger::Call>,
+    debugger: Option<Box<dyn Debugger>>,
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>,
 }
 
 
 impl Interpreter {
 
+    /// An interpreter with all of the pure standard library loaded.
+    ///
+    /// Use `Interpreter::builder` to choose the standard library and capabilities.
     pub fn new() -> Self {
-        let mut globals = Environment::new();
-        lib::load(&mut globals); 
+        Self::builder().with_stdlib(lib::StdlibSet::all()).build()
+    }
+
+    pub fn builder() -> InterpreterBuilder {
+        InterpreterBuilder::new()
+    }
+
+    fn with_globals(globals: Environment) -> Self {
         Self {
             env: globals.clone(),
+            memory: globals.memory(),
             globals: globals,
-            locals: HashMap::new()
+            fuel: None,
+            interrupt: Arc::new(AtomicBool::new(false)),
+            output: Output::Stdout,
+            span: Span::empty(),
+            calls: Vec::new(),
+            debugger: None,
+            profiler: None,
+            coverage: None,
         }
     }
 
     pub fn with_env(env: Environment) -> Self {
         Self {
             env: env.extend(),
+            memory: env.memory(),
             globals: env,
-            locals: HashMap::new()
+            fuel: None,
+            interrupt: Arc::new(AtomicBool::new(false)),
+            output: Output::Stdout,
+            span: Span::empty(),
+            calls: Vec::new(),
+            debugger: None,
+            profiler: None,
+            coverage: None,
         }
     }
 
-    pub fn run(&mut self, program: &Program) -> Result<Option<LuxValue>, RuntimeError> {
-        self.eval_stmts(&program.statements)
+    /// The global scope, containing the standard library and all top level definitions.
+    pub fn globals(&self) -> &Environment {
+        &self.globals
     }
 
+    /// Where the last runtime error happened.
+    pub fn error_span(&self) -> Span {
+        self.span
+    }
+
+    /// Send the output of `print` somewhere else than stdout.
+    pub fn set_output(&mut self, output: Output) {
+        self.output = output;
+    }
+
+    /// Limit the number of steps the interpreter may take before failing with
+    /// `RuntimeError::BudgetExhausted`. A step is a statement or a function call.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    /// The number of steps left, or `None` if there is no limit.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// A flag that can be set from another thread to stop the running program.
+    ///
+    /// The interpreter checks the flag before every step and fails with
+    /// `RuntimeError::Interrupted` once it is set. The flag is not cleared
+    /// automatically, so the host must reset it before running again.
+    pub fn interrupt_handle(&self) -> Arc<AtomicBool> {
+        self.interrupt.clone()
+    }
+
+    /// Limit the estimated number of bytes held by the program's variables
+    /// and closures. Going over the limit fails with `RuntimeError::OutOfMemory`.
+    ///
+    /// Only stored values are counted. Temporaries, such as the intermediate
+    /// results of an expression or the arguments of a native call, are not,
+    /// except that concatenating strings checks the size of the result first.
+    /// A program can therefore briefly hold more than the limit.
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.memory.set_limit(limit);
+    }
+
+    /// Estimated number of bytes currently held by the program.
+    pub fn memory_used(&self) -> usize {
+        self.memory.used()
+    }
+
+    /// Whether a debugger, profiler or coverage is watching the statements run.
+    pub fn is_instrumented(&self) -> bool {
+        self.debugger.is_some() || self.profiler.is_some() || self.coverage.is_some()
+    }
+
+    pub(crate) fn check_memory(&self) -> Result<(), RuntimeError> {
+        self.memory.check(0)
+    }
+
+    /// Consume one step of the budget and check for interruption.
+    fn tick(&mut self) -> Result<(), RuntimeError> {
+        if self.interrupt.load(Ordering::Relaxed) {
+            return Err(RuntimeError::Interrupted);
+        }
+        if let Some(fuel) = self.fuel.as_mut() {
+            if *fuel == 0 {
+                return Err(RuntimeError::BudgetExhausted);
+            }
+            *fuel -= 1;
+        }
+        Ok(())
+    }
 
-    pub fn resolve_local(&mut self, id: &str, depth: usize) {
-        self.locals.insert(id.to_string(), depth);
+    pub fn run(&mut self, program: &Program) -> Result<Option<LuxValue>, RuntimeError> {
+        self.eval_stmts(&program.statements)
     }
 
-    pub fn lookup_variable(&mut self, id: &str) -> Option<LuxValue> {
-        if let Some(depth) = self.locals.get(id) {
-            self.env.get_at(id,*depth)
-        } else {
-            self.globals.get(id)
+
+    pub fn lookup_variable(&self, id: &str, slot: &Slot) -> Option<LuxValue> {
+        match slot.get() {
+            Some((depth, index)) => self.env.get_at(depth, index),
+            None => self.globals.get(id),
         }
     }
 
@@ -63,7 +187,7 @@ impl Interpreter {
     // Statements
     //
 
-    pub fn eval_stmts(&mut self, stmts: &Vec<Stmt>) -> Result<Option<LuxValue>, RuntimeError>  {
+    pub fn eval_stmts(&mut self, stmts: &Vec<WithSpan<Stmt>>) -> Result<Option<LuxValue>, RuntimeError>  {
         let mut last_val = None;
         for stmt in stmts {
             last_val = self.eval_stmt(stmt)?;
@@ -71,7 +195,7 @@ impl Interpreter {
         Ok(last_val)
     }
 
-    pub fn eval_stmt_with(&mut self, stmt: &Stmt, new_env: Environment) -> Result<Option<LuxValue>, RuntimeError> {
+    pub fn eval_stmt_with(&mut self, stmt: &WithSpan<Stmt>, new_env: Environment) -> Result<Option<LuxValue>, RuntimeError> {
         let old_env = mem::replace(&mut self.env, new_env);
         let result = self.eval_stmt(stmt);
         self.env = old_env;
@@ -82,32 +206,55 @@ impl Interpreter {
     /// Run a statement and return the last value of the statement.
     /// 
     /// The return value is used by the repl to print the last value of the statement.
-    fn eval_stmt(&mut self, stmt: &Stmt) -> Result<Option<LuxValue>, RuntimeError> {
+    fn eval_stmt(&mut self, stmt: &WithSpan<Stmt>) -> Result<Option<LuxValue>, RuntimeError> {
+        let outer = mem::replace(&mut self.span, stmt.span);
+        if let Some(profiler) = &mut self.profiler {
+            profiler.hit(stmt);
+        }
+        if let Some(coverage) = &self.coverage {
+            coverage.statement(stmt);
+        }
+        let result = self.debug_stmt(stmt).and_then(|_| self.eval_stmt_kind(&stmt.value));
+        // Keep pointing at the failing statement so the error can be located.
+        if matches!(result, Ok(_) | Err(RuntimeError::Return(_))) {
+            self.span = outer;
+        }
+        result
+    }
+
+    fn eval_stmt_kind(&mut self, stmt: &Stmt) -> Result<Option<LuxValue>, RuntimeError> {
+        self.tick()?;
         match stmt {
             Stmt::Return(expr) => {
                 let value = self.eval_expr(expr)?;
                 Err(RuntimeError::Return(value))
             }
             Stmt::Function(name, args, body) => {
-                self.env.define(name.clone(), LuxValue::function(name.clone(), args.clone(), body.clone(), self.env.clone()));
+                let params = args.iter().map(|a| a.value.clone()).collect();
+                self.env.define(name.value.clone(), LuxValue::function(name.clone(), params, body.clone(), self.env.clone()));
+                self.check_memory()?;
                 Ok(None)
             }
             Stmt::Expression(expr) => {self.eval_expr(expr).map(Some)},
             Stmt::Print(expr) => {
                 let val = self.eval_expr(expr)?;
-                println!("{}", val.to_string());
+                self.output.print(&val.to_string());
                 Ok(None)
             }
             Stmt::Var(name, expr) => {
                 let val = self.eval_expr(expr)?;
-                self.env.define(name.clone(), val.clone());
+                self.env.define(name.value.clone(), val.clone());
+                self.check_memory()?;
                 Ok(Some(val))
             }
             Stmt::Block(stmts) => {
-                self.eval_block(stmts, self.env.extend())
+                let env = self.env.extend();
+                self.check_memory()?;
+                self.eval_block(stmts, env)
             }
             Stmt::If(cond, then, else_) => {
                 let cond_val = self.eval_expr(cond)?;
+                self.cover_branch(self.span, cond_val.is_truthy());
                 if cond_val.is_truthy() {
                     self.eval_stmt(then)
                 } else if let Some(else_) = else_ {
@@ -118,16 +265,58 @@ impl Interpreter {
             }
             Stmt::While(cond, body) => {
                 let mut last_val = None;
-                while self.eval_expr(cond)?.is_truthy() {
+                let span = self.span;
+                loop {
+                    let truthy = self.eval_expr(cond)?.is_truthy();
+                    self.cover_branch(span, truthy);
+                    if !truthy {
+                        break;
+                    }
                     last_val = self.eval_stmt(body)?;
                 }
                 Ok(last_val)
             }
+            Stmt::For(init, cond, incr, body) => {
+                let env = self.env.extend();
+                self.check_memory()?;
+                let old_env = mem::replace(&mut self.env, env);
+                let result = self.eval_for(init.as_deref(), cond.as_ref(), incr.as_ref(), body);
+                self.env = old_env;
+                result
+            }
+        }
+    }
+
+    fn eval_for(
+        &mut self,
+        init: Option<&WithSpan<Stmt>>,
+        cond: Option<&Expr>,
+        incr: Option<&Expr>,
+        body: &WithSpan<Stmt>,
+    ) -> Result<Option<LuxValue>, RuntimeError> {
+        if let Some(init) = init {
+            self.eval_stmt(init)?;
+        }
+        let span = self.span;
+        let mut last_val = None;
+        loop {
+            if let Some(cond) = cond {
+                let truthy = self.eval_expr(cond)?.is_truthy();
+                self.cover_branch(span, truthy);
+                if !truthy {
+                    break;
+                }
+            }
+            last_val = self.eval_stmt(body)?;
+            if let Some(incr) = incr {
+                self.eval_expr(incr)?;
+            }
         }
+        Ok(last_val)
     }
 
 
-    pub(crate) fn eval_block(&mut self, stmts: &Vec<Stmt>, new_env: Environment) -> Result<Option<LuxValue>, RuntimeError> {
+    pub(crate) fn eval_block(&mut self, stmts: &Vec<WithSpan<Stmt>>, new_env: Environment) -> Result<Option<LuxValue>, RuntimeError> {
         let old_env = mem::replace(&mut self.env, new_env);
         let result = self.eval_stmts(stmts);
         self.env = old_env;
@@ -162,48 +351,64 @@ impl Interpreter {
                     }
                 };
 
-                if callable.arity() != args.len() {
+                if args.len() < callable.min_arity() || args.len() > callable.arity() {
+                    let expected = if callable.min_arity() == callable.arity() {
+                        callable.arity().to_string()
+                    } else {
+                        format!("{} to {}", callable.min_arity(), callable.arity())
+                    };
                     return Err(RuntimeError::UnsupportedType(format!(
                             "Expected {} arguments, but got {}",
-                            callable.arity(),
+                            expected,
                             args.len()
                     )));
                 }
-            
-                callable.call(self, &args)
+
+                self.tick()?;
+                if let Some(profiler) = &mut self.profiler {
+                    profiler.enter(callable.name(), callable.declared(), callable.is_native());
+                }
+                let result = callable.call(self, &args);
+                if let Some(profiler) = &mut self.profiler {
+                    profiler.exit();
+                }
+                result
             }
-            Expr::LogicalOr(left, right) => {
+            Expr::LogicalOr(left, operator, right) => {
                 let left_val = self.eval_expr(left)?;
+                self.cover_branch(*operator, !left_val.is_truthy());
                 if left_val.is_truthy() {
                     Ok(left_val)
                 } else {
                     self.eval_expr(right)
                 }
             }
-            Expr::LogicalAnd(left, right) => {
+            Expr::LogicalAnd(left, operator, right) => {
                 let left_val = self.eval_expr(left)?;
+                self.cover_branch(*operator, left_val.is_truthy());
                 if !left_val.is_truthy() {
                     Ok(left_val)
                 } else {
                     self.eval_expr(right)
                 }
             }
-            Expr::Assignment(name, expr) => {
+            Expr::Assignment(name, slot, expr) => {
+                let name = &name.value;
                 let val = self.eval_expr(expr)?;
 
-                let success = if let Some(depth) = self.locals.get(name) {
-                    self.env.assign_at(name.clone(), val.clone(), *depth)
-                } else {
-                    self.globals.assign(name.clone(), val.clone())
+                let success = match slot.get() {
+                    Some((depth, index)) => self.env.assign_at(depth, index, val.clone()),
+                    None => self.globals.assign(name, val.clone()),
                 };
 
                 if success {
+                    self.check_memory()?;
                     Ok(val)
                 } else {
                     Err(RuntimeError::UndefinedVariable(name.clone()))
                 }
             }
-            Expr::Variable(name) => self.lookup_variable(name).ok_or(RuntimeError::UndefinedVariable(name.clone())),
+            Expr::Variable(name, slot) => self.lookup_variable(&name.value, slot).ok_or(RuntimeError::UndefinedVariable(name.value.clone())),
             Expr::Number(n) => Ok(LuxValue::Number(*n)),
             Expr::String(s) => Ok(LuxValue::String(s.clone())),
             Expr::True => Ok(LuxValue::Boolean(true)),
@@ -235,7 +440,10 @@ impl Interpreter {
                     // Math
                     BinaryOp::Plus => match (left_val, right_val) {
                         (LuxValue::Number(left), LuxValue::Number(right)) => Ok(LuxValue::Number(left + right)),
-                        (LuxValue::String(left), LuxValue::String(right)) => Ok(LuxValue::String(left + &right)),
+                        (LuxValue::String(left), LuxValue::String(right)) => {
+                            self.memory.check(left.len() + right.len())?;
+                            Ok(LuxValue::String(format!("{}{}", left, right).into()))
+                        }
                         (left, right) => Err(RuntimeError::UnsupportedType(
                             format!(
                                 "Binary `+` operator can only operate over two numbers or two strings. \
@@ -304,4 +512,229 @@ macro_rules! bin_comparison_operator {
         }
     };
 }
-use bin_comparison_operator;
\ No newline at end of file
+use bin_comparison_operator;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{resolver::Resolver, scanner::Scanner};
+
+    /// Parse, resolve and run the given source.
+    fn run_test(source: &str, interpreter: &mut Interpreter) -> Result<Option<LuxValue>, RuntimeError> {
+        let tokens = Scanner::new(source).run();
+        let program = Program::parse(&tokens).expect("Failed to parse");
+        Resolver::new().run(&program).expect("Failed to resolve");
+        interpreter.run(&program)
+    }
+
+    #[test]
+    fn test_infinite_loop_exhausts_fuel() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_fuel(Some(1000));
+        let result = run_test("while (true) {}", &mut interpreter);
+        assert!(matches!(result, Err(RuntimeError::BudgetExhausted)));
+        assert_eq!(interpreter.fuel(), Some(0));
+    }
+
+    #[test]
+    fn test_recursion_exhausts_fuel() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_fuel(Some(100));
+        let result = run_test("fun f() { return f(); } f();", &mut interpreter);
+        assert!(matches!(result, Err(RuntimeError::BudgetExhausted)));
+    }
+
+    #[test]
+    fn test_program_within_budget_runs() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_fuel(Some(1000));
+        let result = run_test("var a = 1; a + 2;", &mut interpreter);
+        assert_eq!(result.unwrap(), Some(LuxValue::number(3.0)));
+        assert_eq!(interpreter.fuel(), Some(998));
+    }
+
+    #[test]
+    fn test_for_loop_runs_body_and_increment() {
+        let mut interpreter = Interpreter::new();
+        let result = run_test("var sum = 0; for (var i = 0; i < 4; i = i + 1) sum = sum + i; sum;", &mut interpreter);
+        assert_eq!(result.unwrap(), Some(LuxValue::number(6.0)));
+    }
+
+    #[test]
+    fn test_for_loop_initializer_is_scoped_to_loop() {
+        let mut interpreter = Interpreter::new();
+        let result = run_test("for (var i = 0; i < 1; i = i + 1) {} i;", &mut interpreter);
+        assert!(matches!(result, Err(RuntimeError::UndefinedVariable(_))));
+    }
+
+    #[test]
+    fn test_locals_with_the_same_name_are_distinct() {
+        let mut interpreter = Interpreter::new();
+        let source = "
+            var a = \"global\";
+            fun outer() {
+                var a = 1;
+                fun inner(b) { var a = b + 1; return a; }
+                { var a = 5; a = inner(a) * 10; }
+                return a + inner(a);
+            }
+            str(outer()) + a;";
+        let result = run_test(source, &mut interpreter);
+        assert_eq!(result.unwrap(), Some(LuxValue::string("3global")));
+    }
+
+    #[test]
+    fn test_string_literals_and_copies_share_their_text() {
+        let mut interpreter = Interpreter::new();
+        let literal = run_test("var a = \"text\"; var b = a; \"text\";", &mut interpreter).unwrap();
+        let globals = interpreter.globals();
+        match (globals.get("b"), literal, globals.get("a")) {
+            (Some(LuxValue::String(b)), Some(LuxValue::String(literal)), Some(LuxValue::String(a))) => {
+                assert!(Rc::ptr_eq(&a, &b));
+                assert!(Rc::ptr_eq(&a, &literal));
+            }
+            other => panic!("expected strings, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_string_growth_runs_out_of_memory() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_memory_limit(Some(interpreter.memory_used() + 4096));
+        let result = run_test("var s = \"a\"; while (true) { s = s + s; }", &mut interpreter);
+        assert!(matches!(result, Err(RuntimeError::OutOfMemory)));
+    }
+
+    #[test]
+    fn test_closures_run_out_of_memory() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_memory_limit(Some(interpreter.memory_used() + 4096));
+        let source = "
+            fun wrap(f) { fun g() { return f; } return g; }
+            var h = clock;
+            while (true) { h = wrap(h); }
+        ";
+        let result = run_test(source, &mut interpreter);
+        assert!(matches!(result, Err(RuntimeError::OutOfMemory)));
+    }
+
+    #[test]
+    fn test_copies_of_a_string_are_counted_once() {
+        let mut interpreter = Interpreter::new();
+        run_test("var big = \"a\"; for (var i = 0; i < 12; i = i + 1) big = big + big;", &mut interpreter).unwrap();
+        let before = interpreter.memory_used();
+        interpreter.set_memory_limit(Some(before + 4096));
+        let source = "
+            fun keep(s) { var copy = s; return copy; }
+            fun capture() { return big; }
+            var a = big;
+            var b = keep(big);
+            for (var i = 0; i < 100; i = i + 1) { var c = big; }";
+        run_test(source, &mut interpreter).unwrap();
+        assert!(interpreter.memory_used() < before + 1024);
+    }
+
+    #[test]
+    fn test_memory_is_released_after_block() {
+        let mut interpreter = Interpreter::new();
+        let before = interpreter.memory_used();
+        run_test("{ var s = \"some string\"; }", &mut interpreter).unwrap();
+        assert_eq!(interpreter.memory_used(), before);
+    }
+
+    #[test]
+    fn test_sandbox_has_no_stdlib() {
+        let mut interpreter = Interpreter::builder().build();
+        let result = run_test("clock();", &mut interpreter);
+        assert!(matches!(result, Err(RuntimeError::UndefinedVariable(_))));
+    }
+
+    #[test]
+    fn test_builder_loads_selected_stdlib() {
+        let mut interpreter = Interpreter::builder().with_stdlib(lib::Stdlib::Math).build();
+        let result = run_test("sqrt(16);", &mut interpreter);
+        assert_eq!(result.unwrap(), Some(LuxValue::number(4.0)));
+        let result = run_test("readFile(\"a.txt\");", &mut interpreter);
+        assert!(matches!(result, Err(RuntimeError::UndefinedVariable(_))));
+    }
+
+    #[test]
+    fn test_allow_fs_reads_and_writes_below_root() {
+        let root = std::env::temp_dir().join(format!("rlux-fs-test-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let mut interpreter = Interpreter::builder().allow_fs(&root).build();
+
+        run_test("writeFile(\"hello.txt\", \"hello\");", &mut interpreter).unwrap();
+        let result = run_test("readFile(\"hello.txt\");", &mut interpreter);
+        assert_eq!(result.unwrap(), Some(LuxValue::string("hello")));
+        let result = run_test("readFile(\"../hello.txt\");", &mut interpreter);
+        assert!(matches!(result, Err(RuntimeError::PermissionDenied(_))));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_snapshot_isolates_closures() {
+        let mut prelude = Interpreter::new();
+        let source = "
+            fun makeCounter() { var i = 0; fun count() { i = i + 1; return i; } return count; }
+            var counter = makeCounter();
+        ";
+        run_test(source, &mut prelude).unwrap();
+        let snapshot = prelude.snapshot();
+
+        let mut first = snapshot.interpreter();
+        run_test("counter();", &mut first).unwrap();
+        assert_eq!(run_test("counter();", &mut first).unwrap(), Some(LuxValue::number(2.0)));
+
+        let mut second = snapshot.interpreter();
+        assert_eq!(run_test("counter();", &mut second).unwrap(), Some(LuxValue::number(1.0)));
+        assert_eq!(run_test("counter();", &mut prelude).unwrap(), Some(LuxValue::number(1.0)));
+    }
+
+    #[test]
+    fn test_restore_discards_new_globals() {
+        let mut interpreter = Interpreter::new();
+        run_test("var a = 1;", &mut interpreter).unwrap();
+        let snapshot = interpreter.snapshot();
+        run_test("var b = 2; a = 3;", &mut interpreter).unwrap();
+
+        interpreter.restore(&snapshot);
+        assert_eq!(run_test("a;", &mut interpreter).unwrap(), Some(LuxValue::number(1.0)));
+        assert!(matches!(run_test("b;", &mut interpreter), Err(RuntimeError::UndefinedVariable(_))));
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut interpreter = Interpreter::new();
+        let result = run_test("jsonParse(jsonStringify(\"a\" + \"b\"));", &mut interpreter);
+        assert_eq!(result.unwrap(), Some(LuxValue::string("ab")));
+        let result = run_test("jsonStringify(clock);", &mut interpreter);
+        assert!(matches!(result, Err(RuntimeError::TypeError(_))));
+        let result = run_test("jsonStringify(\"a\", 2);", &mut interpreter);
+        assert_eq!(result.unwrap(), Some(LuxValue::string("\"a\"")));
+        let result = run_test("jsonStringify(\"a\", 1.5);", &mut interpreter);
+        assert!(matches!(result, Err(RuntimeError::TypeError(_))));
+    }
+
+    #[test]
+    fn test_interrupted_program_stops() {
+        let mut interpreter = Interpreter::new();
+        interpreter.interrupt_handle().store(true, Ordering::Relaxed);
+        let result = run_test("while (true) {}", &mut interpreter);
+        assert!(matches!(result, Err(RuntimeError::Interrupted)));
+    }
+
+    #[test]
+    fn test_interrupt_from_another_thread() {
+        let mut interpreter = Interpreter::new();
+        let handle = interpreter.interrupt_handle();
+        let thread = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            handle.store(true, Ordering::Relaxed);
+        });
+        let result = run_test("while (true) {}", &mut interpreter);
+        thread.join().unwrap();
+        assert!(matches!(result, Err(RuntimeError::Interrupted)));
+    }
+}
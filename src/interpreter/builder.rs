@@ -0,0 +1,76 @@
+use std::path::PathBuf;
+
+use super::lib::{self, StdlibSet};
+use super::{Environment, Interpreter, Output};
+
+/// Configures which capabilities and limits an `Interpreter` gets.
+///
+/// A builder starts out as a pure sandbox: no standard library, no access to
+/// the outside world and no limits.
+///
+/// ```
+/// use rlux::interpreter::{Interpreter, lib::Stdlib};
+/// let interpreter = Interpreter::builder()
+///     .with_stdlib(Stdlib::Core | Stdlib::Math)
+///     .fuel(10_000)
+///     .build();
+/// assert_eq!(interpreter.fuel(), Some(10_000));
+/// ```
+#[derive(Debug, Default)]
+pub struct InterpreterBuilder {
+    stdlib: StdlibSet,
+    fs_root: Option<PathBuf>,
+    fuel: Option<u64>,
+    memory_limit: Option<usize>,
+    output: Output,
+}
+
+impl InterpreterBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the given standard library modules.
+    pub fn with_stdlib<S: Into<StdlibSet>>(mut self, modules: S) -> Self {
+        self.stdlib = self.stdlib | modules.into();
+        self
+    }
+
+    /// Allow scripts to read and write files below `root`.
+    pub fn allow_fs<P: Into<PathBuf>>(mut self, root: P) -> Self {
+        self.fs_root = Some(root.into());
+        self
+    }
+
+    /// See `Interpreter::set_fuel`.
+    pub fn fuel(mut self, fuel: u64) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    /// See `Interpreter::set_memory_limit`.
+    pub fn memory_limit(mut self, bytes: usize) -> Self {
+        self.memory_limit = Some(bytes);
+        self
+    }
+
+    /// See `Interpreter::set_output`.
+    pub fn output(mut self, output: Output) -> Self {
+        self.output = output;
+        self
+    }
+
+    pub fn build(self) -> Interpreter {
+        let mut globals = Environment::new();
+        lib::load(&mut globals, self.stdlib);
+        if let Some(root) = self.fs_root {
+            lib::load_fs(&mut globals, root);
+        }
+
+        let mut interpreter = Interpreter::with_globals(globals);
+        interpreter.set_fuel(self.fuel);
+        interpreter.set_memory_limit(self.memory_limit);
+        interpreter.set_output(self.output);
+        interpreter
+    }
+}
@@ -0,0 +1,371 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use super::Interpreter;
+use crate::ast::{Expr, Stmt};
+use crate::position::{LineOffsets, Span, WithSpan};
+use crate::program::Program;
+
+/// Records which statements and branches ran, see `Interpreter::set_coverage`.
+///
+/// Every `if`, loop condition and logical operator is a branch point with two
+/// branches: branch 0 runs the then branch, the loop body or the right side of
+/// the operator, branch 1 the else branch, the loop exit or the short circuit.
+///
+/// Clones share what they record, so interpreters started from a snapshot of a
+/// covered interpreter add to the same coverage.
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    hits: Rc<RefCell<Hits>>,
+}
+
+#[derive(Debug, Default)]
+struct Hits {
+    /// By the offset the statement starts at.
+    statements: HashMap<usize, u64>,
+    /// By the offset of the branch point and the branch.
+    branches: HashMap<(usize, usize), u64>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn statement(&self, stmt: &WithSpan<Stmt>) {
+        // A block is covered through its statements.
+        if !matches!(stmt.value, Stmt::Block(_)) {
+            *self.hits.borrow_mut().statements.entry(stmt.span.start.0).or_default() += 1;
+        }
+    }
+
+    /// Record that the branch point at `at` took branch 0 if `first`, and branch 1 otherwise.
+    pub(crate) fn branch(&self, at: Span, first: bool) {
+        let branch = if first { 0 } else { 1 };
+        *self.hits.borrow_mut().branches.entry((at.start.0, branch)).or_default() += 1;
+    }
+
+    /// What ran of a program, with the lines of `source`.
+    pub fn file(&self, path: &str, source: &str, program: &Program) -> FileCoverage {
+        let mut collector = Collector {
+            line_offsets: LineOffsets::new(source),
+            hits: &self.hits.borrow(),
+            file: FileCoverage { path: path.to_string(), statements: vec![], branches: vec![] },
+        };
+        collector.stmts(&program.statements);
+        collector.file
+    }
+}
+
+/// Coverage of one file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileCoverage {
+    pub path: String,
+    /// The line and the number of executions of each statement.
+    pub statements: Vec<(usize, u64)>,
+    pub branches: Vec<Branch>,
+}
+
+/// One of the two branches of a branch point.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Branch {
+    pub line: usize,
+    /// The branch point, numbered in the order they appear in the file.
+    pub block: usize,
+    pub branch: usize,
+    pub taken: u64,
+}
+
+impl FileCoverage {
+    /// The lines with statements and how often their busiest statement ran.
+    pub fn lines(&self) -> BTreeMap<usize, u64> {
+        let mut lines = BTreeMap::new();
+        for &(line, hits) in &self.statements {
+            let entry = lines.entry(line).or_default();
+            *entry = hits.max(*entry);
+        }
+        lines
+    }
+
+    /// The lines with a statement that never ran.
+    pub fn missed_lines(&self) -> Vec<usize> {
+        let mut lines: Vec<usize> = self.statements.iter().filter(|(_, hits)| *hits == 0).map(|(line, _)| *line).collect();
+        lines.sort();
+        lines.dedup();
+        lines
+    }
+
+    pub fn statements_hit(&self) -> usize {
+        self.statements.iter().filter(|(_, hits)| *hits > 0).count()
+    }
+
+    pub fn branches_hit(&self) -> usize {
+        self.branches.iter().filter(|branch| branch.taken > 0).count()
+    }
+
+    /// The file as an lcov tracefile record.
+    pub fn write_lcov<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "TN:")?;
+        writeln!(out, "SF:{}", self.path)?;
+        for (i, branch) in self.branches.iter().enumerate() {
+            // lcov writes `-` for branches whose branch point never ran.
+            let other = &self.branches[i ^ 1];
+            let taken = match (branch.taken, other.taken) {
+                (0, 0) => "-".to_string(),
+                (taken, _) => taken.to_string(),
+            };
+            writeln!(out, "BRDA:{},{},{},{}", branch.line, branch.block, branch.branch, taken)?;
+        }
+        writeln!(out, "BRF:{}", self.branches.len())?;
+        writeln!(out, "BRH:{}", self.branches_hit())?;
+        let lines = self.lines();
+        for (line, hits) in &lines {
+            writeln!(out, "DA:{},{}", line, hits)?;
+        }
+        writeln!(out, "LF:{}", lines.len())?;
+        writeln!(out, "LH:{}", lines.values().filter(|hits| **hits > 0).count())?;
+        writeln!(out, "end_of_record")
+    }
+}
+
+/// Write the files as an lcov tracefile.
+pub fn write_lcov<W: Write>(files: &[FileCoverage], out: &mut W) -> io::Result<()> {
+    for file in files {
+        file.write_lcov(out)?;
+    }
+    Ok(())
+}
+
+/// One line per file with its statement and branch coverage and the lines it missed, then a total.
+pub fn summary<W: Write>(files: &[FileCoverage], out: &mut W) -> io::Result<()> {
+    fn ratio(hit: usize, found: usize) -> String {
+        let percent = if found == 0 { 100.0 } else { hit as f64 * 100.0 / found as f64 };
+        format!("{}/{} ({:.1}%)", hit, found, percent)
+    }
+
+    let (mut statements, mut statements_hit, mut branches, mut branches_hit) = (0, 0, 0, 0);
+    for file in files {
+        writeln!(
+            out,
+            "{}: statements {}, branches {}",
+            file.path,
+            ratio(file.statements_hit(), file.statements.len()),
+            ratio(file.branches_hit(), file.branches.len())
+        )?;
+        let missed = file.missed_lines();
+        if !missed.is_empty() {
+            let missed: Vec<String> = missed.iter().map(|line| line.to_string()).collect();
+            writeln!(out, "    missed lines: {}", missed.join(", "))?;
+        }
+        statements += file.statements.len();
+        statements_hit += file.statements_hit();
+        branches += file.branches.len();
+        branches_hit += file.branches_hit();
+    }
+    writeln!(
+        out,
+        "total: statements {}, branches {}",
+        ratio(statements_hit, statements),
+        ratio(branches_hit, branches)
+    )
+}
+
+/// Walks a program in source order, pairing its statements and branch points with their hits.
+struct Collector<'a> {
+    line_offsets: LineOffsets,
+    hits: &'a Hits,
+    file: FileCoverage,
+}
+
+impl Collector<'_> {
+    fn branch_point(&mut self, at: Span) {
+        let line = self.line_offsets.line(at.start);
+        let block = self.file.branches.len() / 2;
+        for branch in 0..2 {
+            let taken = self.hits.branches.get(&(at.start.0, branch)).copied().unwrap_or(0);
+            self.file.branches.push(Branch { line, block, branch, taken });
+        }
+    }
+
+    fn stmts(&mut self, stmts: &[WithSpan<Stmt>]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &WithSpan<Stmt>) {
+        if !matches!(stmt.value, Stmt::Block(_)) {
+            let hits = self.hits.statements.get(&stmt.span.start.0).copied().unwrap_or(0);
+            self.file.statements.push((self.line_offsets.line(stmt.span.start), hits));
+        }
+        match &stmt.value {
+            Stmt::Expression(expr) | Stmt::Print(expr) | Stmt::Return(expr) | Stmt::Var(_, expr) => self.expr(expr),
+            Stmt::If(cond, then, else_) => {
+                self.branch_point(stmt.span);
+                self.expr(cond);
+                self.stmt(then);
+                if let Some(else_) = else_ {
+                    self.stmt(else_);
+                }
+            }
+            Stmt::While(cond, body) => {
+                self.branch_point(stmt.span);
+                self.expr(cond);
+                self.stmt(body);
+            }
+            Stmt::For(init, cond, incr, body) => {
+                if let Some(init) = init {
+                    self.stmt(init);
+                }
+                if let Some(cond) = cond {
+                    self.branch_point(stmt.span);
+                    self.expr(cond);
+                }
+                if let Some(incr) = incr {
+                    self.expr(incr);
+                }
+                self.stmt(body);
+            }
+            Stmt::Block(stmts) => self.stmts(stmts),
+            Stmt::Function(_, _, body) => self.stmt(body),
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::LogicalOr(left, operator, right) | Expr::LogicalAnd(left, operator, right) => {
+                self.branch_point(*operator);
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Grouping(expr) | Expr::Unary(_, expr) | Expr::Assignment(_, _, expr) => self.expr(expr),
+            Expr::Binary(left, _, right) => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Call(callee, arguments) => {
+                self.expr(callee);
+                for argument in arguments {
+                    self.expr(argument);
+                }
+            }
+            Expr::Number(_) | Expr::String(_) | Expr::True | Expr::False | Expr::Nil | Expr::Variable(..) => {}
+        }
+    }
+}
+
+impl Interpreter {
+    /// Start recording which statements and branches run, or stop with `None`.
+    pub fn set_coverage(&mut self, coverage: Option<Coverage>) {
+        self.coverage = coverage;
+    }
+
+    pub(crate) fn cover_branch(&self, at: Span, first: bool) {
+        if let Some(coverage) = &self.coverage {
+            coverage.branch(at, first);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Output;
+    use crate::scanner::Scanner;
+    use crate::test_runner;
+
+    fn cover(source: &str) -> FileCoverage {
+        let coverage = Coverage::new();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Output::captured());
+        interpreter.set_coverage(Some(coverage.clone()));
+        crate::run(source, &mut interpreter);
+        let program = Program::parse(&Scanner::new(source).run()).unwrap();
+        coverage.file("t.lux", source, &program)
+    }
+
+    fn taken(file: &FileCoverage) -> Vec<(usize, u64)> {
+        file.branches.iter().map(|b| (b.line, b.taken)).collect()
+    }
+
+    #[test]
+    fn test_statements_and_branches() {
+        let source = "\
+var a = 1;
+if (a > 0) {
+  print a;
+} else {
+  print -a;
+}
+while (a < 3) a = a + 1;
+";
+        let file = cover(source);
+        assert_eq!(file.statements, vec![(1, 1), (2, 1), (3, 1), (5, 0), (7, 1), (7, 2)]);
+        assert_eq!(taken(&file), vec![(2, 1), (2, 0), (7, 2), (7, 1)]);
+        assert_eq!(file.missed_lines(), vec![5]);
+    }
+
+    #[test]
+    fn test_short_circuits() {
+        let source = "\
+fun f(a, b) {
+  return a and
+    b or false;
+}
+f(true, true);
+f(true, false);
+f(false, true);
+";
+        // The `or` is on the line of its keyword, not the line the expression starts on.
+        // It evaluates `false` unless `a and b` is true, `and` evaluates `b` if `a` is true.
+        assert_eq!(taken(&cover(source)), vec![(3, 2), (3, 1), (2, 2), (2, 1)]);
+    }
+
+    #[test]
+    fn test_lcov() {
+        let mut out = Vec::new();
+        write_lcov(&[cover("var a = 1;\nif (a) print a;")], &mut out).unwrap();
+        let expected = "\
+TN:
+SF:t.lux
+BRDA:2,0,0,1
+BRDA:2,0,1,0
+BRF:2
+BRH:1
+DA:1,1
+DA:2,1
+LF:2
+LH:2
+end_of_record
+";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+
+        // Branches of a branch point that never ran are marked `-`.
+        let mut out = Vec::new();
+        cover("if (false) {\n  if (true) print 1;\n}").write_lcov(&mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("BRDA:2,1,0,-\nBRDA:2,1,1,-\n"));
+    }
+
+    #[test]
+    fn test_includes_test_functions() {
+        let source = "\
+fun sign(n) {
+  if (n < 0) return -1;
+  return 1;
+}
+fun test_negative() {
+  assertEqual(sign(-2), -1);
+}
+";
+        let coverage = Coverage::new();
+        let outcomes = test_runner::run_source_with("t", source, Some(&coverage));
+        assert!(outcomes.iter().all(|o| o.passed()));
+        let program = Program::parse(&Scanner::new(source).run()).unwrap();
+        let file = coverage.file("t.lux", source, &program);
+        assert_eq!(file.missed_lines(), vec![3]);
+        assert_eq!(file.branches_hit(), 1);
+    }
+}
@@ -0,0 +1,92 @@
+use std::fmt;
+
+use super::{Environment, Interpreter, RuntimeError};
+use crate::ast::Stmt;
+use crate::position::{Span, WithSpan};
+
+/// Lets a host watch and pause a running program, see `Interpreter::set_debugger`.
+pub trait Debugger {
+    /// Called before every statement runs. The program waits until this
+    /// returns, and stops with the error if it fails.
+    fn before_stmt(&mut self, interpreter: &Interpreter, stmt: &WithSpan<Stmt>) -> Result<(), RuntimeError>;
+}
+
+impl fmt::Debug for dyn Debugger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Debugger")
+    }
+}
+
+/// A function call that has not returned yet, as seen by the debugger.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// The name of the function, `<script>` for the top level.
+    pub function: String,
+    /// The statement the frame is running.
+    pub span: Span,
+    /// The innermost scope of the frame.
+    pub env: Environment,
+}
+
+/// What the interpreter remembers about the caller when entering a function.
+#[derive(Debug, Clone)]
+pub(crate) struct Call {
+    function: String,
+    span: Span,
+    env: Environment,
+}
+
+impl Interpreter {
+    /// Call the debugger before every statement, or stop calling it with `None`.
+    pub fn set_debugger(&mut self, debugger: Option<Box<dyn Debugger>>) {
+        self.debugger = debugger;
+    }
+
+    /// The scope of the statement being run.
+    pub fn environment(&self) -> &Environment {
+        &self.env
+    }
+
+    /// The number of functions being called.
+    pub fn call_depth(&self) -> usize {
+        self.calls.len()
+    }
+
+    /// The calls being run, the innermost first and the top level last.
+    pub fn call_stack(&self) -> Vec<Frame> {
+        let functions = self.calls.iter().map(|c| c.function.clone()).rev();
+        let functions = functions.chain(std::iter::once("<script>".to_string()));
+        let spans = std::iter::once(self.span).chain(self.calls.iter().rev().map(|c| c.span));
+        let envs = std::iter::once(self.env.clone()).chain(self.calls.iter().rev().map(|c| c.env.clone()));
+
+        functions
+            .zip(spans)
+            .zip(envs)
+            .map(|((function, span), env)| Frame { function, span, env })
+            .collect()
+    }
+
+    pub(crate) fn push_call(&mut self, function: &str) {
+        self.calls.push(Call {
+            function: function.to_string(),
+            span: self.span,
+            env: self.env.clone(),
+        });
+    }
+
+    pub(crate) fn pop_call(&mut self) {
+        self.calls.pop();
+    }
+
+    /// Give the debugger a chance to pause before the statement.
+    pub(crate) fn debug_stmt(&mut self, stmt: &WithSpan<Stmt>) -> Result<(), RuntimeError> {
+        match self.debugger.take() {
+            Some(mut debugger) => {
+                let result = debugger.before_stmt(self, stmt);
+                self.debugger = Some(debugger);
+                result
+            }
+            None => Ok(()),
+        }
+    }
+}
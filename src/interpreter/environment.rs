@@ -1,57 +1,126 @@
 use core::fmt;
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{cell::RefCell, collections::HashMap, mem, rc::Rc};
 
-use super::LuxValue;
+use super::{LuxValue, Memory};
 
 
 
-#[derive(Clone)]
+/// A scope. Local scopes hold their variables in the order they are defined
+/// and are read by the index the resolver gave each variable. The global scope
+/// also keeps a table to find its variables by name.
 struct EnvNode {
-    vars: HashMap<String, LuxValue>,
+    names: Vec<Rc<str>>,
+    values: Vec<LuxValue>,
+    /// Index of each global by name, `None` in local scopes.
+    globals: Option<HashMap<Rc<str>, usize>>,
     parent: Option<Rc<RefCell<EnvNode>>>,
+    memory: Rc<Memory>,
+    /// Estimated number of bytes held by this node itself, released on drop
+    /// together with the values it holds.
+    size: usize,
 }
 
 impl EnvNode {
-    pub fn new() -> Self {
-        Self { vars: HashMap::new(), parent: None }
+    pub fn new(memory: Rc<Memory>) -> Self {
+        Self::allocate(Some(HashMap::new()), None, memory)
     }
 
     pub fn with_parent(parent: Rc<RefCell<EnvNode>>) -> Self {
-        Self { vars: HashMap::new(), parent: Some(parent) }
+        let memory = parent.borrow().memory.clone();
+        Self::allocate(None, Some(parent), memory)
+    }
+
+    fn allocate(globals: Option<HashMap<Rc<str>, usize>>, parent: Option<Rc<RefCell<EnvNode>>>, memory: Rc<Memory>) -> Self {
+        let size = mem::size_of::<EnvNode>();
+        memory.allocate(size);
+        Self { names: Vec::new(), values: Vec::new(), globals, parent, memory, size }
+    }
+
+
+    /// The index of the variable named `name` in this scope. A local scope
+    /// can define a name twice, the later definition hides the earlier one.
+    fn index(&self, name: &str) -> Option<usize> {
+        match &self.globals {
+            Some(globals) => globals.get(name).copied(),
+            None => self.names.iter().rposition(|n| &**n == name),
+        }
+    }
+
+    /// Defining a name again replaces a global but adds a new local.
+    pub fn define(&mut self, name: Rc<str>, value: LuxValue) {
+        if let Some(index) = self.globals.as_ref().and_then(|globals| globals.get(&name).copied()) {
+            self.set(index, value);
+            return;
+        }
+        // The name is shared with the program, only the slot itself is new.
+        let slot = mem::size_of::<(Rc<str>, LuxValue)>();
+        self.memory.allocate(slot);
+        self.size += slot;
+        self.memory.hold(&value);
+        if let Some(globals) = &mut self.globals {
+            globals.insert(name.clone(), self.values.len());
+        }
+        self.names.push(name);
+        self.values.push(value);
     }
-    
-    pub fn define(&mut self, name: String, value: LuxValue) {
-        self.vars.insert(name, value);
+
+    fn set(&mut self, index: usize, value: LuxValue) {
+        self.memory.hold(&value);
+        self.memory.release(&self.values[index]);
+        self.values[index] = value;
     }
 
-    pub fn assign(&mut self, name: String, value: LuxValue) -> bool {
-        if !self.vars.contains_key(&name) {
-            return match self.parent {
+    pub fn assign(&mut self, name: &str, value: LuxValue) -> bool {
+        match self.index(name) {
+            Some(index) => {
+                self.set(index, value);
+                true
+            }
+            None => match self.parent {
                 Some(ref p) => p.borrow_mut().assign(name, value),
                 None => false,
-            }
+            },
         }
-        self.vars.insert(name, value);
-        return true;
     }
 
     pub fn get(&self, name: &str) -> Option<LuxValue> {
-        match self.vars.get(name) {
-            Some(v) => Some(v.clone()),
+        match self.index(name) {
+            Some(index) => Some(self.values[index].clone()),
             None => match &self.parent {
                 Some(p) => p.borrow().get(name),
                 None => None,
             }
         }
     }
+
+    fn get_at(&self, depth: usize, index: usize) -> Option<LuxValue> {
+        match depth {
+            0 => self.values.get(index).cloned(),
+            _ => self.parent.as_ref()?.borrow().get_at(depth - 1, index),
+        }
+    }
+
+    fn assign_at(&mut self, depth: usize, index: usize, value: LuxValue) -> bool {
+        match depth {
+            0 if index < self.values.len() => {
+                self.set(index, value);
+                true
+            }
+            0 => false,
+            _ => match &self.parent {
+                Some(p) => p.borrow_mut().assign_at(depth - 1, index, value),
+                None => false,
+            },
+        }
+    }
 }
 
 
 impl fmt::Debug for EnvNode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut debug_struct = f.debug_struct("EnvNode");
-        // Print the `vars` map
-        debug_struct.field("vars", &self.vars);
+        let vars: Vec<_> = self.names.iter().zip(&self.values).collect();
+        debug_struct.field("vars", &vars);
 
         // Print the `parent` if it exists
         if let Some(parent) = &self.parent {
@@ -64,6 +133,15 @@ impl fmt::Debug for EnvNode {
     }
 }
 
+impl Drop for EnvNode {
+    fn drop(&mut self) {
+        for value in &self.values {
+            self.memory.release(value);
+        }
+        self.memory.free(self.size);
+    }
+}
+
 
 #[derive(Clone)]
 pub struct Environment {
@@ -72,7 +150,17 @@ pub struct Environment {
 
 impl Environment {
     pub fn new() -> Self {
-        Self { node: Rc::new(RefCell::new(EnvNode::new())) }
+        Self::with_memory(Rc::new(Memory::new()))
+    }
+
+    /// Create an environment whose allocations are accounted in `memory`.
+    pub fn with_memory(memory: Rc<Memory>) -> Self {
+        Self { node: Rc::new(RefCell::new(EnvNode::new(memory))) }
+    }
+
+    /// The memory book-keeping shared by this environment and all its children.
+    pub fn memory(&self) -> Rc<Memory> {
+        self.node.borrow().memory.clone()
     }
 
 
@@ -89,41 +177,86 @@ impl Environment {
         })
     }
 
-    pub fn assign(&mut self, name: String, value: LuxValue) -> bool {
+    pub fn assign(&mut self, name: &str, value: LuxValue) -> bool {
         self.node.borrow_mut().assign(name, value)
     }
-    
-    pub fn assign_at(&mut self, name: String, value: LuxValue, depth: usize) -> bool {
-        if let Some(mut ancestor) = self.ancestor(depth) {
-            ancestor.assign(name, value)
-        } else {
-            false
-        }
+
+    /// Assign to the local at `index` in the scope `depth` scopes out.
+    pub fn assign_at(&mut self, depth: usize, index: usize, value: LuxValue) -> bool {
+        self.node.borrow_mut().assign_at(depth, index, value)
     }
 
     pub fn get(&self, name: &str) -> Option<LuxValue> {
         self.node.borrow().get(name)
     }
 
-    pub fn define(&mut self, name: String, value: LuxValue) {
-        self.node.borrow_mut().define(name, value);
+    pub fn define<N: Into<Rc<str>>>(&mut self, name: N, value: LuxValue) {
+        self.node.borrow_mut().define(name.into(), value);
     }
 
-    pub fn get_at(&self, name: &str, depth: usize) -> Option<LuxValue> {
-        let ancestor = self.ancestor(depth)?;
-        ancestor.get(name)
+    /// The local at `index` in the scope `depth` scopes out.
+    pub fn get_at(&self, depth: usize, index: usize) -> Option<LuxValue> {
+        self.node.borrow().get_at(depth, index)
     }
 
-    fn ancestor(&self, depth: usize) -> Option<Environment> {
-        let mut current = self.clone();
-        for _ in 0..depth {
-            current = current.pop()?;
-        }
-        Some(current)
+    /// The variables defined directly in this environment, sorted by name.
+    pub fn variables(&self) -> Vec<(String, LuxValue)> {
+        let node = self.node.borrow();
+        let mut vars: Vec<(String, LuxValue)> = node
+            .names
+            .iter()
+            .enumerate()
+            .filter(|&(i, name)| node.index(name) == Some(i))
+            .map(|(i, name)| (name.to_string(), node.values[i].clone()))
+            .collect();
+        vars.sort_by(|(a, _), (b, _)| a.cmp(b));
+        vars
     }
+}
+
 
+/// Copies environments such that the copy shares no mutable state with the original.
+///
+/// Closures capture the environment they were defined in, so copying a value
+/// can require copying environments and vice versa. The copier remembers every
+/// environment it has copied so that shared and cyclic references (a function
+/// stored in the environment it closes over) are preserved in the copy.
+pub struct EnvCopier {
+    memory: Rc<Memory>,
+    copies: HashMap<*const RefCell<EnvNode>, Environment>,
 }
 
+impl EnvCopier {
+    /// Copies are accounted in `memory`.
+    pub fn new(memory: Rc<Memory>) -> Self {
+        Self { memory, copies: HashMap::new() }
+    }
+
+    pub fn copy(&mut self, env: &Environment) -> Environment {
+        let key = Rc::as_ptr(&env.node);
+        if let Some(copy) = self.copies.get(&key) {
+            return copy.clone();
+        }
+
+        let parent = env.pop().map(|parent| self.copy(&parent));
+        let mut copy = match parent {
+            Some(parent) => parent.extend(),
+            None => Environment::with_memory(self.memory.clone()),
+        };
+        self.copies.insert(key, copy.clone());
+
+        // Locals are copied in order, so that they keep their indices.
+        let (names, values) = {
+            let node = env.node.borrow();
+            (node.names.clone(), node.values.clone())
+        };
+        for (name, value) in names.into_iter().zip(values) {
+            let value = value.deep_copy(self);
+            copy.define(name, value);
+        }
+        copy
+    }
+}
 
 impl fmt::Debug for Environment {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -139,16 +272,16 @@ mod tests {
     #[test]
     fn test_can_define_and_get_variables() {
         let mut env = Environment::new();
-        env.define("a".to_string(), LuxValue::String("a".to_string()));
-        assert_eq!(env.get("a"), Some(LuxValue::String("a".to_string())));
+        env.define("a".to_string(), LuxValue::string("a"));
+        assert_eq!(env.get("a"), Some(LuxValue::string("a")));
     }
 
     #[test]
     fn test_can_assign_and_get_variables() {
         let mut env = Environment::new();
-        env.define("a".to_string(), LuxValue::String("a".to_string()));
-        env.assign("a".to_string(), LuxValue::String("b".to_string()));
-        assert_eq!(env.get("a"), Some(LuxValue::String("b".to_string())));
+        env.define("a".to_string(), LuxValue::string("a"));
+        env.assign("a", LuxValue::string("b"));
+        assert_eq!(env.get("a"), Some(LuxValue::string("b")));
     }
 
     #[test]
@@ -164,23 +297,79 @@ mod tests {
         let mut env = Environment::new();
         env.define("a".to_string(), LuxValue::string("a"));
         let mut child = env.extend();
-        child.assign("a".to_string(), LuxValue::string("b"));
+        child.assign("a", LuxValue::string("b"));
         assert_eq!(env.get("a"), Some(LuxValue::string("b")));
     }
 
 
     #[test]
-    fn test_can_assign_at_depth() {
+    fn test_can_get_and_assign_at_slot() {
         let mut env = Environment::new();
-        env.define("a".to_string(), LuxValue::string("a"));
+        env.define("a", LuxValue::string("global"));
+        let mut outer = env.extend();
+        outer.define("a", LuxValue::string("a"));
+        outer.define("b", LuxValue::string("b"));
+        let mut inner = outer.extend();
+        inner.define("a", LuxValue::string("c"));
+        inner.assign_at(1, 0, LuxValue::string("d"));
+
+        assert_eq!(inner.get_at(0, 0), Some(LuxValue::string("c")));
+        assert_eq!(inner.get_at(1, 0), Some(LuxValue::string("d")));
+        assert_eq!(inner.get_at(1, 1), Some(LuxValue::string("b")));
+        assert_eq!(inner.get_at(1, 2), None);
+        assert_eq!(env.get("a"), Some(LuxValue::string("global")));
+    }
+
+    #[test]
+    fn test_redefining_adds_locals_but_replaces_globals() {
+        let mut env = Environment::new();
+        env.define("a", LuxValue::number(1.0));
+        env.define("a", LuxValue::number(2.0));
         let mut child = env.extend();
-        child.define("a".to_string(), LuxValue::string("b"));
-        child.assign_at("a".to_string(), LuxValue::string("c"),1);
+        child.define("a", LuxValue::number(3.0));
+        child.define("a", LuxValue::number(4.0));
 
+        assert_eq!(env.variables(), vec![("a".to_string(), LuxValue::number(2.0))]);
+        assert_eq!(child.variables(), vec![("a".to_string(), LuxValue::number(4.0))]);
+        assert_eq!(child.get_at(0, 0), Some(LuxValue::number(3.0)));
+        assert_eq!(child.get("a"), Some(LuxValue::number(4.0)));
+    }
 
-        assert_eq!(env.get("a"), Some(LuxValue::string("c")));
-        assert_eq!(child.get("a"), Some(LuxValue::string("b")));
-        assert_eq!(child.get_at("a", 1), Some(LuxValue::string("c")));
+    #[test]
+    fn test_copy_does_not_share_variables() {
+        let mut env = Environment::new();
+        env.define("a".to_string(), LuxValue::string("a"));
+        let child = env.extend();
+
+        let mut copier = EnvCopier::new(Rc::new(Memory::new()));
+        let mut copy = copier.copy(&child);
+        copy.assign("a", LuxValue::string("b"));
+
+        assert_eq!(child.get("a"), Some(LuxValue::string("a")));
+        assert_eq!(copy.get("a"), Some(LuxValue::string("b")));
+    }
+
+    #[test]
+    fn test_memory_is_released_when_env_is_dropped() {
+        let env = Environment::new();
+        let memory = env.memory();
+        let before = memory.used();
+        {
+            let mut child = env.extend();
+            child.define("a".to_string(), LuxValue::string("a long string value"));
+            assert!(memory.used() > before);
+        }
+        assert_eq!(memory.used(), before);
+    }
+
+    #[test]
+    fn test_memory_follows_reassignment() {
+        let mut env = Environment::new();
+        let memory = env.memory();
+        env.define("a".to_string(), LuxValue::string("a"));
+        let small = memory.used();
+        env.assign("a", LuxValue::string("a much longer string"));
+        assert_eq!(memory.used(), small + "a much longer string".len() - 1);
     }
 
 }
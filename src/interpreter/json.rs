@@ -0,0 +1,114 @@
+//! Conversion between lux values and JSON.
+//!
+//! Lux has no lists or maps yet, so only scalars (`nil`, booleans, numbers and
+//! strings) can be converted. Arrays, objects and functions are reported as
+//! `RuntimeError::TypeError`.
+
+use serde::Serialize;
+use serde_json::{ser::PrettyFormatter, Serializer, Value};
+
+use super::{LuxValue, RuntimeError};
+
+impl TryFrom<&LuxValue> for Value {
+    type Error = RuntimeError;
+
+    fn try_from(value: &LuxValue) -> Result<Self, Self::Error> {
+        match value {
+            LuxValue::Nil => Ok(Value::Null),
+            LuxValue::Boolean(b) => Ok(Value::Bool(*b)),
+            // Whole numbers are written without a fraction, like lux prints them.
+            LuxValue::Number(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => Ok(Value::from(*n as i64)),
+            LuxValue::Number(n) => serde_json::Number::from_f64(*n)
+                .map(Value::Number)
+                .ok_or_else(|| RuntimeError::TypeError(format!("Cannot convert `{}` to JSON", n))),
+            LuxValue::String(s) => Ok(Value::String(s.to_string())),
+            LuxValue::Callable(callable) => Err(RuntimeError::TypeError(format!(
+                "Cannot convert function `{}` to JSON",
+                callable
+            ))),
+        }
+    }
+}
+
+impl TryFrom<Value> for LuxValue {
+    type Error = RuntimeError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Null => Ok(LuxValue::Nil),
+            Value::Bool(b) => Ok(LuxValue::Boolean(b)),
+            Value::Number(n) => n
+                .as_f64()
+                .map(LuxValue::Number)
+                .ok_or_else(|| RuntimeError::TypeError(format!("JSON number `{}` is out of range", n))),
+            Value::String(s) => Ok(LuxValue::String(s.into())),
+            Value::Array(_) => Err(RuntimeError::TypeError(
+                "JSON arrays are not supported, lux has no lists".to_string(),
+            )),
+            Value::Object(_) => Err(RuntimeError::TypeError(
+                "JSON objects are not supported, lux has no maps".to_string(),
+            )),
+        }
+    }
+}
+
+/// Parse a JSON document into a lux value.
+pub fn parse(source: &str) -> Result<LuxValue, RuntimeError> {
+    let value: Value = serde_json::from_str(source)
+        .map_err(|err| RuntimeError::TypeError(format!("Invalid JSON: {}", err)))?;
+    LuxValue::try_from(value)
+}
+
+/// Convert a lux value to JSON, pretty printed with `indent` spaces if given.
+pub fn stringify(value: &LuxValue, indent: Option<usize>) -> Result<String, RuntimeError> {
+    let value = Value::try_from(value)?;
+    match indent {
+        None => Ok(value.to_string()),
+        Some(indent) => {
+            let indent = " ".repeat(indent);
+            let mut out = Vec::new();
+            let mut serializer =
+                Serializer::with_formatter(&mut out, PrettyFormatter::with_indent(indent.as_bytes()));
+            value
+                .serialize(&mut serializer)
+                .map_err(|err| RuntimeError::TypeError(err.to_string()))?;
+            Ok(String::from_utf8(out).expect("JSON is valid UTF-8"))
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_scalars() {
+        assert_eq!(parse("null").unwrap(), LuxValue::nil());
+        assert_eq!(parse("true").unwrap(), LuxValue::t());
+        assert_eq!(parse("1.5").unwrap(), LuxValue::number(1.5));
+        assert_eq!(parse("\"a\\nb\"").unwrap(), LuxValue::string("a\nb"));
+    }
+
+    #[test]
+    fn test_parse_rejects_collections_and_invalid_json() {
+        assert!(matches!(parse("[1, 2]"), Err(RuntimeError::TypeError(_))));
+        assert!(matches!(parse("{\"a\": 1}"), Err(RuntimeError::TypeError(_))));
+        assert!(matches!(parse("{"), Err(RuntimeError::TypeError(_))));
+    }
+
+    #[test]
+    fn test_stringify() {
+        assert_eq!(stringify(&LuxValue::string("a\"b"), None).unwrap(), "\"a\\\"b\"");
+        assert_eq!(stringify(&LuxValue::number(3.0), None).unwrap(), "3");
+        assert_eq!(stringify(&LuxValue::number(0.5), None).unwrap(), "0.5");
+        assert_eq!(stringify(&LuxValue::nil(), Some(2)).unwrap(), "null");
+        assert!(stringify(&LuxValue::number(f64::NAN), None).is_err());
+    }
+
+    #[test]
+    fn test_stringify_rejects_functions() {
+        let clock = LuxValue::native_function("clock", 0, |_| Ok(LuxValue::nil()));
+        assert!(matches!(stringify(&clock, None), Err(RuntimeError::TypeError(_))));
+    }
+}
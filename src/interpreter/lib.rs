@@ -1,11 +1,139 @@
 //! Standard library for lux. All definitions are available in the global scope.
 //!
-//!
+//! The library is split into modules so that an embedder can choose what a
+//! script is allowed to do. Pure modules are selected with [`Stdlib`], while
+//! modules that reach outside of the interpreter (like the file system) must
+//! be granted explicitly through the [`InterpreterBuilder`](super::InterpreterBuilder).
 
-use super::{Environment, LuxValue, RuntimeError};
+use super::{json, Environment, LuxValue, RuntimeError};
+use std::fs;
+use std::io;
+use std::ops::BitOr;
+use std::path::{Component, Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 
+/// A pure module of the standard library.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stdlib {
+    /// `type` and `str`
+    Core,
+    /// `abs`, `floor` and `sqrt`
+    Math,
+    /// `clock`
+    Time,
+    /// `jsonParse` and `jsonStringify`
+    Json,
+    /// `assert` and `assertEqual`
+    Assert,
+}
+
+impl Stdlib {
+    fn bit(self) -> u8 {
+        match self {
+            Stdlib::Core => 1,
+            Stdlib::Math => 1 << 1,
+            Stdlib::Time => 1 << 2,
+            Stdlib::Json => 1 << 3,
+            Stdlib::Assert => 1 << 4,
+        }
+    }
+}
+
+/// A set of standard library modules, built with `|`.
+///
+/// ```
+/// use rlux::interpreter::lib::{Stdlib, StdlibSet};
+/// let set = Stdlib::Core | Stdlib::Math;
+/// assert!(set.contains(Stdlib::Math));
+/// assert!(!set.contains(Stdlib::Time));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StdlibSet(u8);
+
+impl StdlibSet {
+    pub const fn none() -> Self {
+        StdlibSet(0)
+    }
+
+    pub fn all() -> Self {
+        Stdlib::Core | Stdlib::Math | Stdlib::Time | Stdlib::Json | Stdlib::Assert
+    }
+
+    pub fn contains(&self, module: Stdlib) -> bool {
+        self.0 & module.bit() != 0
+    }
+}
+
+impl From<Stdlib> for StdlibSet {
+    fn from(module: Stdlib) -> Self {
+        StdlibSet(module.bit())
+    }
+}
+
+impl BitOr for Stdlib {
+    type Output = StdlibSet;
+
+    fn bitor(self, rhs: Stdlib) -> StdlibSet {
+        StdlibSet::from(self) | rhs
+    }
+}
+
+impl BitOr<Stdlib> for StdlibSet {
+    type Output = StdlibSet;
+
+    fn bitor(self, rhs: Stdlib) -> StdlibSet {
+        StdlibSet(self.0 | rhs.bit())
+    }
+}
+
+impl BitOr for StdlibSet {
+    type Output = StdlibSet;
+
+    fn bitor(self, rhs: StdlibSet) -> StdlibSet {
+        StdlibSet(self.0 | rhs.0)
+    }
+}
+
+// Core
+
+/// The name of the type of a value
+fn type_of(args: &[LuxValue]) -> Result<LuxValue, RuntimeError> {
+    Ok(LuxValue::string(args[0].type_name()))
+}
+
+/// Convert any value to its string representation
+fn str(args: &[LuxValue]) -> Result<LuxValue, RuntimeError> {
+    Ok(LuxValue::String(args[0].to_string().into()))
+}
+
+// Math
+
+fn number_arg(name: &str, value: &LuxValue) -> Result<f64, RuntimeError> {
+    match value {
+        LuxValue::Number(n) => Ok(*n),
+        other => Err(RuntimeError::TypeError(format!(
+            "`{}` expects a number, got `{}`",
+            name,
+            other.type_name()
+        ))),
+    }
+}
+
+fn abs(args: &[LuxValue]) -> Result<LuxValue, RuntimeError> {
+    Ok(LuxValue::Number(number_arg("abs", &args[0])?.abs()))
+}
+
+fn floor(args: &[LuxValue]) -> Result<LuxValue, RuntimeError> {
+    Ok(LuxValue::Number(number_arg("floor", &args[0])?.floor()))
+}
+
+fn sqrt(args: &[LuxValue]) -> Result<LuxValue, RuntimeError> {
+    Ok(LuxValue::Number(number_arg("sqrt", &args[0])?.sqrt()))
+}
+
+// Time
+
 /// Read the current time in milliseconds
 fn clock(_: &[LuxValue]) -> Result<LuxValue, RuntimeError> {
     let start = SystemTime::now();
@@ -19,8 +147,219 @@ fn clock(_: &[LuxValue]) -> Result<LuxValue, RuntimeError> {
     Ok(super::LuxValue::Number(in_ms as f64))
 }
 
-/// Load the standard library into an environment.
-pub fn load(env: &mut Environment) {
-    let clock = LuxValue::native_function("clock", 0, clock);
-    env.define("clock".to_string(), clock);
-}
\ No newline at end of file
+// Json
+
+fn json_parse(args: &[LuxValue]) -> Result<LuxValue, RuntimeError> {
+    match &args[0] {
+        LuxValue::String(source) => json::parse(source),
+        other => Err(RuntimeError::TypeError(format!(
+            "`jsonParse` expects a string, got `{}`",
+            other.type_name()
+        ))),
+    }
+}
+
+/// Serialize the value, indented by the optional number of spaces
+fn json_stringify(args: &[LuxValue]) -> Result<LuxValue, RuntimeError> {
+    let indent = match args.get(1) {
+        Some(indent) => {
+            let indent = number_arg("jsonStringify", indent)?;
+            if indent < 0.0 || indent.fract() != 0.0 {
+                return Err(RuntimeError::TypeError(format!(
+                    "`jsonStringify` expects a whole number of spaces, got `{}`",
+                    indent
+                )));
+            }
+            Some(indent as usize)
+        }
+        None => None,
+    };
+    json::stringify(&args[0], indent).map(|s| LuxValue::String(s.into()))
+}
+
+// Assert
+
+/// Fail with the optional message unless the condition is truthy
+fn assert(args: &[LuxValue]) -> Result<LuxValue, RuntimeError> {
+    if args[0].is_truthy() {
+        return Ok(LuxValue::Nil);
+    }
+    let message = match args.get(1) {
+        Some(message) => message.to_string(),
+        None => format!("assertion failed, got `{:?}`", args[0]),
+    };
+    Err(RuntimeError::AssertionFailed(message))
+}
+
+fn assert_equal(args: &[LuxValue]) -> Result<LuxValue, RuntimeError> {
+    if args[0] == args[1] {
+        return Ok(LuxValue::Nil);
+    }
+    Err(RuntimeError::AssertionFailed(format!(
+        "expected `{:?}` to equal `{:?}`",
+        args[0], args[1]
+    )))
+}
+
+// File system
+
+/// Resolve a path given by a script against the sandbox root.
+///
+/// Absolute paths and paths that climb out of the root with `..` are rejected,
+/// and so are paths that leave the root through a symlink.
+fn sandboxed_path(root: &Path, path: &LuxValue) -> Result<PathBuf, RuntimeError> {
+    let path = join_below(root, path)?;
+    let outside = || RuntimeError::PermissionDenied(format!("Path `{}` is outside of the allowed directory", path.display()));
+    let unresolved = |err: io::Error| RuntimeError::Io(format!("Could not resolve `{}`: {}", path.display(), err));
+
+    let root = root.canonicalize().map_err(unresolved)?;
+    let real = match path.canonicalize() {
+        Ok(real) => real,
+        // Something is there but can't be resolved, such as a dangling symlink.
+        Err(_) if fs::symlink_metadata(&path).is_ok() => return Err(outside()),
+        // A file about to be written does not exist yet, but its directory must.
+        Err(err) => match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => parent.canonicalize().map_err(|_| unresolved(err))?.join(name),
+            _ => return Err(unresolved(err)),
+        },
+    };
+    if real.starts_with(&root) {
+        Ok(real)
+    } else {
+        Err(outside())
+    }
+}
+
+/// Join a path to the root without looking at the file system.
+fn join_below(root: &Path, path: &LuxValue) -> Result<PathBuf, RuntimeError> {
+    let path = match path {
+        LuxValue::String(path) => Path::new(&**path),
+        other => {
+            return Err(RuntimeError::TypeError(format!(
+                "Expected a path as a string, got `{}`",
+                other.type_name()
+            )))
+        }
+    };
+
+    let mut resolved = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => resolved.push(part),
+            Component::CurDir => {}
+            Component::ParentDir if resolved.pop() => {}
+            _ => {
+                return Err(RuntimeError::PermissionDenied(format!(
+                    "Path `{}` is outside of the allowed directory",
+                    path.display()
+                )))
+            }
+        }
+    }
+    Ok(root.join(resolved))
+}
+
+fn read_file(root: &Path, args: &[LuxValue]) -> Result<LuxValue, RuntimeError> {
+    let path = sandboxed_path(root, &args[0])?;
+    fs::read_to_string(&path)
+        .map(|s| LuxValue::String(s.into()))
+        .map_err(|err| RuntimeError::Io(format!("Could not read `{}`: {}", path.display(), err)))
+}
+
+fn write_file(root: &Path, args: &[LuxValue]) -> Result<LuxValue, RuntimeError> {
+    let path = sandboxed_path(root, &args[0])?;
+    fs::write(&path, args[1].to_string())
+        .map(|_| LuxValue::Nil)
+        .map_err(|err| RuntimeError::Io(format!("Could not write `{}`: {}", path.display(), err)))
+}
+
+/// Load the selected modules of the standard library into an environment.
+pub fn load(env: &mut Environment, modules: StdlibSet) {
+    if modules.contains(Stdlib::Core) {
+        env.define("type".to_string(), LuxValue::native_function("type", 1, type_of));
+        env.define("str".to_string(), LuxValue::native_function("str", 1, str));
+    }
+    if modules.contains(Stdlib::Math) {
+        env.define("abs".to_string(), LuxValue::native_function("abs", 1, abs));
+        env.define("floor".to_string(), LuxValue::native_function("floor", 1, floor));
+        env.define("sqrt".to_string(), LuxValue::native_function("sqrt", 1, sqrt));
+    }
+    if modules.contains(Stdlib::Time) {
+        let clock = LuxValue::native_function("clock", 0, clock);
+        env.define("clock".to_string(), clock);
+    }
+    if modules.contains(Stdlib::Json) {
+        env.define("jsonParse".to_string(), LuxValue::native_function("jsonParse", 1, json_parse));
+        env.define(
+            "jsonStringify".to_string(),
+            LuxValue::native_function_with_optional("jsonStringify", 1, 2, json_stringify),
+        );
+    }
+    if modules.contains(Stdlib::Assert) {
+        env.define("assert".to_string(), LuxValue::native_function_with_optional("assert", 1, 2, assert));
+        env.define("assertEqual".to_string(), LuxValue::native_function("assertEqual", 2, assert_equal));
+    }
+}
+
+/// Give scripts read and write access to the files below `root`.
+pub fn load_fs(env: &mut Environment, root: PathBuf) {
+    let read_root = root.clone();
+    env.define(
+        "readFile".to_string(),
+        LuxValue::native_closure("readFile", 1, move |args| read_file(&read_root, args)),
+    );
+    env.define(
+        "writeFile".to_string(),
+        LuxValue::native_closure("writeFile", 2, move |args| write_file(&root, args)),
+    );
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_only_selected_modules() {
+        let mut env = Environment::new();
+        load(&mut env, Stdlib::Math.into());
+        assert!(env.get("sqrt").is_some());
+        assert!(env.get("clock").is_none());
+        assert!(env.get("str").is_none());
+    }
+
+    #[test]
+    fn test_sandboxed_path_stays_below_root() {
+        let root = Path::new("/sandbox");
+        let path = join_below(root, &LuxValue::string("a/../b.txt")).unwrap();
+        assert_eq!(path, Path::new("/sandbox/b.txt"));
+        assert!(join_below(root, &LuxValue::string("../etc/passwd")).is_err());
+        assert!(join_below(root, &LuxValue::string("/etc/passwd")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sandboxed_path_rejects_symlinks_out_of_root() {
+        let dir = std::env::temp_dir().join(format!("rlux-symlink-test-{}", std::process::id()));
+        let root = dir.join("root");
+        let outside = dir.join("outside");
+        fs::create_dir_all(&root).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(outside.join("secret.txt"), "secret").unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("escape")).unwrap();
+        std::os::unix::fs::symlink(outside.join("new.txt"), root.join("dangling")).unwrap();
+
+        let denied = |path: &str| matches!(sandboxed_path(&root, &LuxValue::string(path)), Err(RuntimeError::PermissionDenied(_)));
+        assert!(denied("escape/secret.txt"));
+        assert!(denied("escape/new.txt"));
+        assert!(denied("dangling"));
+        assert!(matches!(read_file(&root, &[LuxValue::string("escape/secret.txt")]), Err(RuntimeError::PermissionDenied(_))));
+        assert!(write_file(&root, &[LuxValue::string("escape/new.txt"), LuxValue::string("x")]).is_err());
+        assert!(!outside.join("new.txt").exists());
+
+        let inside = sandboxed_path(&root, &LuxValue::string("new.txt")).unwrap();
+        assert_eq!(inside, root.canonicalize().unwrap().join("new.txt"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,117 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+use super::{LuxValue, RuntimeError};
+
+/// Book-keeping of how much memory a program is holding on to.
+///
+/// The numbers are estimates: environments report the size of their
+/// variables and release it again when they are dropped. Strings and
+/// functions are shared between the variables holding them, so their size
+/// is counted once, while at least one variable holds them.
+#[derive(Debug, Default)]
+pub struct Memory {
+    used: Cell<usize>,
+    limit: Cell<Option<usize>>,
+    /// How many variables hold each shared allocation, by address.
+    holders: RefCell<HashMap<*const u8, usize>>,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn used(&self) -> usize {
+        self.used.get()
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit.get()
+    }
+
+    pub fn set_limit(&self, limit: Option<usize>) {
+        self.limit.set(limit);
+    }
+
+    pub fn allocate(&self, bytes: usize) {
+        self.used.set(self.used.get().saturating_add(bytes));
+    }
+
+    pub fn free(&self, bytes: usize) {
+        self.used.set(self.used.get().saturating_sub(bytes));
+    }
+
+    /// Count `value` as held by one more variable.
+    pub fn hold(&self, value: &LuxValue) {
+        if let Some((address, size)) = value.allocation() {
+            let mut holders = self.holders.borrow_mut();
+            let count = holders.entry(address).or_default();
+            if *count == 0 {
+                self.allocate(size);
+            }
+            *count += 1;
+        }
+    }
+
+    /// Count `value` as held by one variable less, freeing it with its last holder.
+    pub fn release(&self, value: &LuxValue) {
+        if let Some((address, size)) = value.allocation() {
+            let mut holders = self.holders.borrow_mut();
+            if let Some(count) = holders.get_mut(&address) {
+                *count -= 1;
+                if *count == 0 {
+                    holders.remove(&address);
+                    self.free(size);
+                }
+            }
+        }
+    }
+
+    /// Check that `extra` more bytes can be allocated without going over the limit.
+    pub fn check(&self, extra: usize) -> Result<(), RuntimeError> {
+        match self.limit.get() {
+            Some(limit) if self.used.get().saturating_add(extra) > limit => Err(RuntimeError::OutOfMemory),
+            _ => Ok(()),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allocate_and_free() {
+        let memory = Memory::new();
+        memory.allocate(10);
+        memory.allocate(5);
+        memory.free(10);
+        assert_eq!(memory.used(), 5);
+    }
+
+    #[test]
+    fn test_check_respects_limit() {
+        let memory = Memory::new();
+        memory.allocate(10);
+        assert!(memory.check(1000).is_ok());
+        memory.set_limit(Some(16));
+        assert!(memory.check(6).is_ok());
+        assert!(matches!(memory.check(7), Err(RuntimeError::OutOfMemory)));
+    }
+
+    #[test]
+    fn test_shared_values_are_counted_once() {
+        let memory = Memory::new();
+        let a = LuxValue::string("some text");
+        let b = a.clone();
+        memory.hold(&a);
+        memory.hold(&b);
+        assert_eq!(memory.used(), 9);
+        memory.release(&a);
+        assert_eq!(memory.used(), 9);
+        memory.release(&b);
+        assert_eq!(memory.used(), 0);
+    }
+}
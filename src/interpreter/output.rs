@@ -0,0 +1,46 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Where `print` writes to.
+///
+/// Captured output is shared between clones, so a host can keep one clone
+/// and read what the program printed.
+///
+/// ```
+/// use rlux::interpreter::{Interpreter, Output};
+/// let output = Output::captured();
+/// let mut interpreter = Interpreter::builder().output(output.clone()).build();
+/// rlux::run("print 1; print \"two\";", &mut interpreter);
+/// assert_eq!(output.take(), "1\ntwo\n");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub enum Output {
+    #[default]
+    Stdout,
+    Captured(Rc<RefCell<String>>),
+}
+
+impl Output {
+    pub fn captured() -> Self {
+        Output::Captured(Rc::new(RefCell::new(String::new())))
+    }
+
+    pub fn print(&self, line: &str) {
+        match self {
+            Output::Stdout => println!("{}", line),
+            Output::Captured(buffer) => {
+                let mut buffer = buffer.borrow_mut();
+                buffer.push_str(line);
+                buffer.push('\n');
+            }
+        }
+    }
+
+    /// Everything captured so far, leaving the buffer empty. Always empty for stdout.
+    pub fn take(&self) -> String {
+        match self {
+            Output::Stdout => String::new(),
+            Output::Captured(buffer) => buffer.take(),
+        }
+    }
+}
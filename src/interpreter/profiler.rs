@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use super::Interpreter;
+use crate::ast::Stmt;
+use crate::position::{BytePos, LineOffsets, WithSpan};
+
+/// The name of the outermost frame, running the script's top level.
+const SCRIPT: &str = "<script>";
+
+/// Functions are told apart by where they are declared as well as by name,
+/// since nested functions and closures in different places can share a name.
+type FunctionKey = (String, Option<BytePos>);
+
+/// Records calls and statements while a program runs, see `Interpreter::set_profiler`.
+#[derive(Debug)]
+pub struct Profiler {
+    functions: HashMap<FunctionKey, FunctionProfile>,
+    stack: Vec<Active>,
+    /// How many frames of each function are on the stack, so that recursive
+    /// calls count towards the inclusive time only once.
+    depths: HashMap<FunctionKey, usize>,
+    /// Executions of each statement, by the offset it starts at.
+    hits: HashMap<usize, u64>,
+    /// Exclusive time of each call stack, the function names joined by `;`.
+    folded: HashMap<String, Duration>,
+}
+
+/// A call that has not returned yet.
+#[derive(Debug)]
+struct Active {
+    key: FunctionKey,
+    stack: String,
+    start: Instant,
+    /// Time spent in the functions it called.
+    children: Duration,
+}
+
+/// The time spent in one function, over all its calls.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FunctionProfile {
+    pub name: String,
+    /// Where the function is declared, `None` for natives and the top level.
+    pub declared: Option<BytePos>,
+    pub native: bool,
+    pub calls: u64,
+    /// Including the time spent in the functions it called.
+    pub inclusive: Duration,
+    /// Only the time spent in the function itself.
+    pub exclusive: Duration,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    /// Start profiling, timing the top level from now on.
+    pub fn new() -> Self {
+        let mut profiler = Profiler {
+            functions: HashMap::new(),
+            stack: Vec::new(),
+            depths: HashMap::new(),
+            hits: HashMap::new(),
+            folded: HashMap::new(),
+        };
+        profiler.enter(SCRIPT, None, false);
+        profiler
+    }
+
+    pub(crate) fn hit(&mut self, stmt: &WithSpan<Stmt>) {
+        // A block is counted through its statements, not on the line it opens.
+        if !matches!(stmt.value, Stmt::Block(_)) {
+            *self.hits.entry(stmt.span.start.0).or_default() += 1;
+        }
+    }
+
+    pub(crate) fn enter(&mut self, name: &str, declared: Option<BytePos>, native: bool) {
+        let key = (name.to_string(), declared);
+        let function = self.functions.entry(key.clone()).or_insert_with(|| FunctionProfile {
+            name: name.to_string(),
+            declared,
+            native,
+            ..FunctionProfile::default()
+        });
+        function.calls += 1;
+        *self.depths.entry(key.clone()).or_default() += 1;
+
+        let stack = match self.stack.last() {
+            Some(caller) => format!("{};{}", caller.stack, name),
+            None => name.to_string(),
+        };
+        self.stack.push(Active { key, stack, start: Instant::now(), children: Duration::ZERO });
+    }
+
+    pub(crate) fn exit(&mut self) {
+        let Some(call) = self.stack.pop() else {
+            return;
+        };
+        let elapsed = call.start.elapsed();
+        let exclusive = elapsed.saturating_sub(call.children);
+        if let Some(caller) = self.stack.last_mut() {
+            caller.children += elapsed;
+        }
+        *self.folded.entry(call.stack).or_default() += exclusive;
+
+        let depth = self.depths.get_mut(&call.key).expect("entered functions have a depth");
+        *depth -= 1;
+        let function = self.functions.get_mut(&call.key).expect("entered functions are recorded");
+        function.exclusive += exclusive;
+        if *depth == 0 {
+            function.inclusive += elapsed;
+        }
+    }
+
+    /// Stop profiling, ending the calls that have not returned, such as the top level.
+    pub fn finish(mut self) -> Profile {
+        while !self.stack.is_empty() {
+            self.exit();
+        }
+
+        let mut functions: Vec<FunctionProfile> = self.functions.into_values().collect();
+        functions.sort_by(|a, b| {
+            b.inclusive.cmp(&a.inclusive).then_with(|| a.name.cmp(&b.name)).then(a.declared.cmp(&b.declared))
+        });
+        let mut folded: Vec<(String, Duration)> = self.folded.into_iter().collect();
+        folded.sort();
+        Profile { functions, hits: self.hits, folded }
+    }
+}
+
+/// What a profiled program spent its time on.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    /// Sorted by inclusive time, the slowest first.
+    pub functions: Vec<FunctionProfile>,
+    hits: HashMap<usize, u64>,
+    /// Exclusive time of each call stack, sorted by stack.
+    pub folded: Vec<(String, Duration)>,
+}
+
+impl Profile {
+    /// How many statements ran on each line of `source`, the busiest lines first.
+    pub fn line_hits(&self, source: &str) -> Vec<(usize, u64)> {
+        let line_offsets = LineOffsets::new(source);
+        let mut lines: HashMap<usize, u64> = HashMap::new();
+        for (&offset, &hits) in &self.hits {
+            *lines.entry(line_offsets.line(BytePos(offset))).or_default() += hits;
+        }
+        let mut lines: Vec<(usize, u64)> = lines.into_iter().collect();
+        lines.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        lines
+    }
+
+    /// A table of the functions followed by a table of the lines of `source`.
+    pub fn report<W: Write>(&self, source: &str, out: &mut W) -> io::Result<()> {
+        let line_offsets = LineOffsets::new(source);
+        writeln!(out, "{:>8} {:>14} {:>14}  function", "calls", "inclusive ms", "exclusive ms")?;
+        for function in &self.functions {
+            writeln!(
+                out,
+                "{:>8} {:>14.3} {:>14.3}  {}{}",
+                function.calls,
+                function.inclusive.as_secs_f64() * 1000.0,
+                function.exclusive.as_secs_f64() * 1000.0,
+                function.name,
+                match function.declared {
+                    Some(pos) => format!(" (line {})", line_offsets.line(pos)),
+                    None if function.native => " (native)".to_string(),
+                    None => String::new(),
+                }
+            )?;
+        }
+
+        let lines: Vec<&str> = source.lines().collect();
+        writeln!(out, "\n{:>8} {:>6}  source", "hits", "line")?;
+        for (line, hits) in self.line_hits(source) {
+            let text = lines.get(line - 1).map_or("", |l| l.trim());
+            writeln!(out, "{:>8} {:>6}  {}", hits, line, text)?;
+        }
+        Ok(())
+    }
+
+    /// One line per call stack with its exclusive time in microseconds, as read by flame graph tools.
+    pub fn write_folded<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for (stack, time) in &self.folded {
+            writeln!(out, "{} {}", stack, time.as_micros())?;
+        }
+        Ok(())
+    }
+}
+
+impl Interpreter {
+    /// Start recording calls and statements, or stop with `None`.
+    pub fn set_profiler(&mut self, profiler: Option<Profiler>) {
+        self.profiler = profiler;
+    }
+
+    /// Stop profiling and return the profiler, if one was set.
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Output;
+
+    fn profile(source: &str) -> Profile {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Output::captured());
+        interpreter.set_profiler(Some(Profiler::new()));
+        crate::run(source, &mut interpreter);
+        interpreter.take_profiler().unwrap().finish()
+    }
+
+    const SOURCE: &str = "\
+fun fib(n) {
+  if (n < 2) return n;
+  return fib(n - 1) + fib(n - 2);
+}
+var start = clock();
+print fib(5);
+";
+
+    #[test]
+    fn test_counts_calls_and_lines() {
+        let profile = profile(SOURCE);
+        let calls: Vec<(&str, u64, bool)> =
+            profile.functions.iter().map(|f| (f.name.as_str(), f.calls, f.native)).collect();
+        assert_eq!(calls[0], ("<script>", 1, false));
+        assert!(calls.contains(&("fib", 15, false)));
+        assert!(calls.contains(&("clock", 1, true)));
+
+        // Every call runs the `if`, then one of the two `return`s.
+        let hits = profile.line_hits(SOURCE);
+        assert_eq!(hits[0], (2, 15 + 8));
+        assert!(hits.contains(&(3, 7)));
+        assert!(hits.contains(&(6, 1)));
+        assert!(hits.contains(&(1, 1)));
+    }
+
+    #[test]
+    fn test_functions_with_the_same_name_are_kept_apart() {
+        let source = "\
+fun a() { fun inner() {} inner(); }
+fun b() { fun inner() {} inner(); inner(); }
+a();
+b();
+";
+        let profile = profile(source);
+        let mut inner: Vec<(Option<BytePos>, u64)> =
+            profile.functions.iter().filter(|f| f.name == "inner").map(|f| (f.declared, f.calls)).collect();
+        inner.sort();
+        assert_eq!(inner, vec![(Some(BytePos(14)), 1), (Some(BytePos(50)), 2)]);
+
+        let mut out = Vec::new();
+        profile.report(source, &mut out).unwrap();
+        let report = String::from_utf8(out).unwrap();
+        assert!(report.contains("  inner (line 1)\n"), "{}", report);
+        assert!(report.contains("  inner (line 2)\n"), "{}", report);
+        assert!(report.contains("  <script>\n"), "{}", report);
+    }
+
+    #[test]
+    fn test_times_add_up() {
+        let profile = profile(SOURCE);
+        let script = &profile.functions[0];
+        for function in &profile.functions {
+            assert!(function.exclusive <= function.inclusive, "{:?}", function);
+            assert!(function.inclusive <= script.inclusive, "{:?}", function);
+        }
+        let total: Duration = profile.functions.iter().map(|f| f.exclusive).sum();
+        assert_eq!(total, profile.folded.iter().map(|(_, time)| *time).sum());
+        assert!(total <= script.inclusive);
+    }
+
+    #[test]
+    fn test_folded_stacks() {
+        let profile = profile(SOURCE);
+        let stacks: Vec<&str> = profile.folded.iter().map(|(stack, _)| stack.as_str()).collect();
+        assert_eq!(stacks, vec![
+            "<script>",
+            "<script>;clock",
+            "<script>;fib",
+            "<script>;fib;fib",
+            "<script>;fib;fib;fib",
+            "<script>;fib;fib;fib;fib",
+            "<script>;fib;fib;fib;fib;fib",
+        ]);
+        let mut out = Vec::new();
+        profile.write_folded(&mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().starts_with("<script> "));
+    }
+}
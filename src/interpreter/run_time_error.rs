@@ -6,5 +6,18 @@ pub enum RuntimeError {
     DivideByZero(String),
     UndefinedVariable(String),
     UnsupportedType(String),
+    /// A native function tried to do something the interpreter is not allowed to.
+    PermissionDenied(String),
+    Io(String),
+    /// The interpreter ran out of fuel before the program finished.
+    BudgetExhausted,
+    /// The host asked the interpreter to stop.
+    Interrupted,
+    /// The user quit the debugger, which stops the program without an error.
+    Quit,
+    /// The program went over its memory limit.
+    OutOfMemory,
+    /// `assert` or `assertEqual` failed.
+    AssertionFailed(String),
     Return(LuxValue)
 }
\ No newline at end of file
@@ -0,0 +1,71 @@
+use std::rc::Rc;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use super::environment::EnvCopier;
+use super::{Interpreter, Memory};
+
+/// A frozen copy of an interpreter's state: globals, defined functions and
+/// resolved locals.
+///
+/// Running a prelude once, taking a snapshot, and then starting every script
+/// from the snapshot avoids re-parsing and re-running the prelude while
+/// guaranteeing that no mutation leaks from one script into the next.
+///
+/// ```
+/// use rlux::interpreter::Interpreter;
+/// let mut interpreter = Interpreter::new();
+/// rlux::run("var count = 0;", &mut interpreter);
+/// let snapshot = interpreter.snapshot();
+///
+/// rlux::run("count = count + 1;", &mut interpreter);
+/// interpreter.restore(&snapshot);
+/// assert_eq!(rlux::run("count;", &mut interpreter).unwrap().to_string(), "0");
+/// ```
+#[derive(Debug)]
+pub struct Snapshot {
+    interpreter: Interpreter,
+}
+
+impl Snapshot {
+    /// A fresh interpreter starting from the snapshot.
+    pub fn interpreter(&self) -> Interpreter {
+        self.interpreter.deep_copy(Arc::new(AtomicBool::new(false)))
+    }
+}
+
+impl Interpreter {
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            interpreter: self.deep_copy(Arc::new(AtomicBool::new(false))),
+        }
+    }
+
+    /// Reset the interpreter to the state of the snapshot.
+    ///
+    /// The interrupt handle is kept, so a host watching this interpreter can
+    /// still stop it after a restore.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        *self = snapshot.interpreter.deep_copy(self.interrupt.clone());
+    }
+
+    fn deep_copy(&self, interrupt: Arc<AtomicBool>) -> Interpreter {
+        let memory = Rc::new(Memory::new());
+        memory.set_limit(self.memory.limit());
+
+        let mut copier = EnvCopier::new(memory.clone());
+        Interpreter {
+            globals: copier.copy(&self.globals),
+            env: copier.copy(&self.env),
+            fuel: self.fuel,
+            interrupt,
+            memory,
+            output: self.output.clone(),
+            span: self.span,
+            calls: Vec::new(),
+            debugger: None,
+            profiler: None,
+            coverage: self.coverage.clone(),
+        }
+    }
+}
@@ -1,10 +1,12 @@
 use core::fmt;
 use std::{
     fmt::{Debug, Display},
+    mem,
     rc::Rc,
 };
 
-use super::{Environment, Interpreter, RuntimeError, Stmt};
+use super::{environment::EnvCopier, Environment, Interpreter, RuntimeError, Stmt};
+use crate::position::{BytePos, WithSpan};
 
 pub trait LuxCallable: Display + Debug {
     fn call(
@@ -13,6 +15,21 @@ pub trait LuxCallable: Display + Debug {
         args: &[LuxValue],
     ) -> Result<LuxValue, RuntimeError>;
     fn arity(&self) -> usize;
+    fn name(&self) -> &str;
+    /// The number of arguments that must be given, the rest up to `arity` are optional.
+    fn min_arity(&self) -> usize {
+        self.arity()
+    }
+    /// Whether the callable is provided by the interpreter instead of written in lux.
+    fn is_native(&self) -> bool {
+        false
+    }
+    /// Where the callable is declared, if it is written in lux.
+    fn declared(&self) -> Option<BytePos> {
+        None
+    }
+    /// Copy the callable together with any environment it has captured.
+    fn deep_copy(self: Rc<Self>, copier: &mut EnvCopier) -> Rc<dyn LuxCallable>;
 }
 
 #[derive(Clone)]
@@ -20,7 +37,8 @@ pub enum LuxValue {
     Nil,
     Boolean(bool),
     Number(f64),
-    String(String),
+    /// Strings are immutable and shared, so copying one is cheap.
+    String(Rc<str>),
     Callable(Rc<dyn LuxCallable>),
 }
 
@@ -48,7 +66,7 @@ impl LuxValue {
     }
 
     pub fn string(s: &str) -> Self {
-        LuxValue::String(s.to_string())
+        LuxValue::String(Rc::from(s))
     }
 
     pub fn callable<T>(callable: T) -> Self
@@ -63,17 +81,42 @@ impl LuxValue {
         arity: usize,
         fn_ptr: fn(args: &[LuxValue]) -> Result<LuxValue, RuntimeError>,
     ) -> Self {
+        Self::native_closure(name, arity, fn_ptr)
+    }
+
+    /// Like `native_function` but the implementation may capture state,
+    /// e.g. the root directory a sandboxed file function is allowed to read.
+    pub fn native_closure<F>(name: &'static str, arity: usize, fun: F) -> Self
+    where
+        F: Fn(&[LuxValue]) -> Result<LuxValue, RuntimeError> + 'static,
+    {
         LuxValue::callable(NativeFunction {
             name: name,
-            fn_ptr: fn_ptr,
+            fun: Box::new(fun),
             arity: arity,
+            min_arity: arity,
+        })
+    }
+
+    /// Like `native_function` but only the first `min_arity` arguments are required.
+    pub fn native_function_with_optional(
+        name: &'static str,
+        min_arity: usize,
+        arity: usize,
+        fn_ptr: fn(args: &[LuxValue]) -> Result<LuxValue, RuntimeError>,
+    ) -> Self {
+        LuxValue::callable(NativeFunction {
+            name,
+            fun: Box::new(fn_ptr),
+            arity,
+            min_arity,
         })
     }
 
     pub fn function(
-        name: String,
-        params: Vec<String>,
-        body: Box<Stmt>,
+        name: WithSpan<Rc<str>>,
+        params: Vec<Rc<str>>,
+        body: Box<WithSpan<Stmt>>,
         env: Environment
     ) -> Self {
         Self::callable(LuxFunction {
@@ -85,6 +128,14 @@ impl LuxValue {
 
     }
 
+    /// Copy the value, see `EnvCopier`.
+    pub fn deep_copy(&self, copier: &mut EnvCopier) -> LuxValue {
+        match self {
+            LuxValue::Callable(callable) => LuxValue::Callable(callable.clone().deep_copy(copier)),
+            other => other.clone(),
+        }
+    }
+
     pub fn is_truthy(&self) -> bool {
         match self {
             LuxValue::Nil => false,
@@ -98,11 +149,21 @@ impl LuxValue {
             LuxValue::Nil => "nil".to_string(),
             LuxValue::Boolean(b) => b.to_string(),
             LuxValue::Number(n) => n.to_string(),
-            LuxValue::String(s) => s.clone(),
+            LuxValue::String(s) => s.to_string(),
             LuxValue::Callable(callable) => format!("{}", callable),
         }
     }
 
+    /// The shared allocation this value points to and its estimated size in
+    /// bytes, used for memory limits. Copies of the value share it.
+    pub fn allocation(&self) -> Option<(*const u8, usize)> {
+        match self {
+            LuxValue::String(s) => Some((s.as_ptr(), s.len())),
+            LuxValue::Callable(callable) => Some((Rc::as_ptr(callable) as *const u8, mem::size_of::<LuxFunction>())),
+            _ => None,
+        }
+    }
+
     pub fn type_name(&self) -> &'static str {
         match self {
             LuxValue::Nil => "nil",
@@ -118,7 +179,7 @@ impl LuxValue {
             (LuxValue::Nil, LuxValue::Nil) => true,
             (LuxValue::Boolean(l), LuxValue::Boolean(r)) => l == r,
             (LuxValue::Number(l), LuxValue::Number(r)) => l == r,
-            (LuxValue::String(l), LuxValue::String(r)) => l == r,
+            (LuxValue::String(l), LuxValue::String(r)) => Rc::ptr_eq(l, r) || l == r,
             (LuxValue::Callable(l), LuxValue::Callable(r)) => Rc::ptr_eq(l, r),
             _ => false,
         }
@@ -154,11 +215,14 @@ impl Debug for LuxValue {
 
 // Native Function 
 
+pub type NativeFn = dyn Fn(&[LuxValue]) -> Result<LuxValue, RuntimeError>;
+
 /// Function provided by the interpreter. Used by the standard library.
 pub struct NativeFunction {
     pub name: &'static str,
-    pub fn_ptr: fn(args: &[LuxValue]) -> Result<LuxValue, RuntimeError>,
+    pub fun: Box<NativeFn>,
     pub arity: usize,
+    pub min_arity: usize,
 }
 
 impl LuxCallable for NativeFunction {
@@ -167,12 +231,29 @@ impl LuxCallable for NativeFunction {
         _: &mut Interpreter,
         args: &[LuxValue],
     ) -> Result<LuxValue, RuntimeError> {
-        (self.fn_ptr)(args)
+        (self.fun)(args)
     }
 
     fn arity(&self) -> usize {
         self.arity
     }
+
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn min_arity(&self) -> usize {
+        self.min_arity
+    }
+
+    fn is_native(&self) -> bool {
+        true
+    }
+
+    fn deep_copy(self: Rc<Self>, _: &mut EnvCopier) -> Rc<dyn LuxCallable> {
+        // Native functions are immutable, so they can be shared.
+        self
+    }
 }
 
 impl Display for NativeFunction {
@@ -185,8 +266,9 @@ impl Debug for NativeFunction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("NativeFunction")
             .field("name", &self.name)
-            .field("fn_ptr", &"fn_ptr")
+            .field("fun", &"fun")
             .field("arity", &self.arity)
+            .field("min_arity", &self.min_arity)
             .finish()
     }
 }
@@ -196,9 +278,9 @@ impl Debug for NativeFunction {
 
 #[derive(Debug, Clone)]
 pub struct FunDecl {
-    pub name: String,
-    pub params: Vec<String>,
-    pub body: Box<Stmt>,
+    pub name: WithSpan<Rc<str>>,
+    pub params: Vec<Rc<str>>,
+    pub body: Box<WithSpan<Stmt>>,
 }
 
 #[derive(Debug, Clone)]
@@ -217,7 +299,11 @@ impl LuxCallable for LuxFunction {
         for (param, value) in self.decl.params.iter().zip(args) {
             env.define(param.clone(), value.clone());
         }
-        let real_returned_value = match interpreter.eval_stmt_with(&self.decl.body, env) {
+        interpreter.check_memory()?;
+        interpreter.push_call(&self.decl.name.value);
+        let result = interpreter.eval_stmt_with(&self.decl.body, env);
+        interpreter.pop_call();
+        let real_returned_value = match result {
             Ok(_) => LuxValue::Nil,
             Err(RuntimeError::Return(value)) => value,
             Err(other) => return Err(other),
@@ -228,10 +314,25 @@ impl LuxCallable for LuxFunction {
     fn arity(&self) -> usize {
         self.decl.params.len()
     }
+
+    fn name(&self) -> &str {
+        &self.decl.name.value
+    }
+
+    fn declared(&self) -> Option<BytePos> {
+        Some(self.decl.name.span.start)
+    }
+
+    fn deep_copy(self: Rc<Self>, copier: &mut EnvCopier) -> Rc<dyn LuxCallable> {
+        Rc::new(LuxFunction {
+            decl: self.decl.clone(),
+            closure: copier.copy(&self.closure),
+        })
+    }
 }
 
 impl Display for LuxFunction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "<fun {}>", self.decl.name)
+        write!(f, "<fun {}>", self.decl.name.value)
     }
 }
\ No newline at end of file
@@ -8,33 +8,69 @@ pub mod stmt_parser;
 pub mod program;
 pub mod interpreter;
 pub mod resolver;
+pub mod repl;
+pub mod check;
+pub mod formatter;
+pub mod dump;
+pub mod test_runner;
+pub mod lsp;
+pub mod debug;
+pub mod dap;
+pub mod lint;
+pub mod optimizer;
+pub mod intern;
+pub mod bench;
 
-use position::{Diagnostic, Span};
+use position::Diagnostic;
 use resolver::Resolver;
 use scanner::Scanner;
-use interpreter::{Interpreter, LuxValue};
+use interpreter::{Interpreter, LuxValue, RuntimeError};
+use program::Program;
+use position::WithSpan;
+use token::Token;
 
+/// Run a script, folding its constants first unless the interpreter reports
+/// on the source as written, with a debugger, profiler or coverage.
 pub fn run(source: &str, interpreter: &mut Interpreter) -> Option<LuxValue> {
+    if interpreter.is_instrumented() {
+        run_with(source, interpreter, Program::parse)
+    } else {
+        run_with(source, interpreter, |tokens| Program::parse(tokens).map(optimizer::optimize))
+    }
+}
+
+/// Like `run`, but the last statement may be an expression without a `;`.
+pub fn run_repl(source: &str, interpreter: &mut Interpreter) -> Option<LuxValue> {
+    run_with(source, interpreter, Program::parse_repl)
+}
+
+fn run_with<P>(source: &str, interpreter: &mut Interpreter, parse: P) -> Option<LuxValue>
+where
+    P: FnOnce(&Vec<WithSpan<Token>>) -> Result<Program, Vec<Diagnostic>>,
+{
     let line_offsets = position::LineOffsets::new(source);
 
     let mut scanner = Scanner::new(source);
 
     let tokens = scanner.run();
 
-    let result = program::Program::parse(&tokens).and_then(|p| {
-            Resolver::new(interpreter).run(&p)?;
+    let result = parse(&tokens).and_then(|p| {
+            Resolver::new().run(&p)?;
             Ok(p)
         }).and_then(|p| {
-            interpreter.run(&p)
-                .map_err(|err| {
+            match interpreter.run(&p) {
+                // Quitting the debugger ends the program early on purpose.
+                Err(RuntimeError::Quit) => Ok(None),
+                result => result.map_err(|err| {
                     vec![
                         Diagnostic {
-                            span: Span::empty(), 
+                            span: interpreter.error_span(),
                             message: format!("{:?}", err)
                         }
                         ]
                     }
-                )
+                ),
+            }
         });
     
     match result {
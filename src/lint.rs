@@ -0,0 +1,453 @@
+//! Lint warnings for lux source: code that runs, but is probably a mistake.
+//!
+//! The rules about names use what the resolver found, the others walk the
+//! syntax tree. Each rule can be turned off with a `RuleSet`.
+
+use serde_json::{json, Value};
+
+use crate::{
+    ast::{BinaryOp, Expr, StructuralPrinter, Stmt},
+    check,
+    interpreter::Interpreter,
+    position::{Diagnostic, LineOffsets, Span, WithSpan},
+    program::Program,
+    resolver::{DefinitionKind, Resolver, ScopeData},
+    scanner::Scanner,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    /// A local variable or function that is never read.
+    UnusedVariable,
+    /// A parameter that is never read.
+    UnusedParameter,
+    /// A definition with the name of a variable in an enclosing scope.
+    Shadowing,
+    /// Statements after a `return`.
+    UnreachableCode,
+    /// Assigning to a variable that is not declared anywhere.
+    UndeclaredGlobal,
+    /// Comparing an expression to itself.
+    SelfComparison,
+    /// A function that returns a value on some paths but not on others.
+    InconsistentReturn,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 7] = [
+        Rule::UnusedVariable,
+        Rule::UnusedParameter,
+        Rule::Shadowing,
+        Rule::UnreachableCode,
+        Rule::UndeclaredGlobal,
+        Rule::SelfComparison,
+        Rule::InconsistentReturn,
+    ];
+
+    /// The name used to turn the rule on and off.
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::UnusedVariable => "unused-variable",
+            Rule::UnusedParameter => "unused-parameter",
+            Rule::Shadowing => "shadowing",
+            Rule::UnreachableCode => "unreachable-code",
+            Rule::UndeclaredGlobal => "undeclared-global",
+            Rule::SelfComparison => "self-comparison",
+            Rule::InconsistentReturn => "inconsistent-return",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Rule> {
+        Rule::ALL.into_iter().find(|rule| rule.name() == name)
+    }
+
+    fn bit(self) -> u8 {
+        1 << Rule::ALL.iter().position(|rule| *rule == self).expect("all rules are listed")
+    }
+}
+
+/// The rules to check.
+///
+/// ```
+/// use rlux::lint::{Rule, RuleSet};
+/// let rules = RuleSet::all().without(Rule::Shadowing);
+/// assert!(rules.contains(Rule::UnusedVariable));
+/// assert!(!rules.contains(Rule::Shadowing));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RuleSet(u8);
+
+impl RuleSet {
+    pub const fn none() -> Self {
+        RuleSet(0)
+    }
+
+    pub fn all() -> Self {
+        Rule::ALL.into_iter().fold(RuleSet::none(), RuleSet::with)
+    }
+
+    pub fn with(self, rule: Rule) -> Self {
+        RuleSet(self.0 | rule.bit())
+    }
+
+    pub fn without(self, rule: Rule) -> Self {
+        RuleSet(self.0 & !rule.bit())
+    }
+
+    pub fn contains(&self, rule: Rule) -> bool {
+        self.0 & rule.bit() != 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub rule: Rule,
+    pub span: Span,
+    pub message: String,
+}
+
+/// The warnings for the source, or its errors if it does not pass `check`.
+pub fn lint(source: &str, rules: RuleSet) -> Result<Vec<Warning>, Vec<Diagnostic>> {
+    let errors = check::check(source);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let program = Program::parse(&Scanner::new(source).run()).expect("checked programs parse");
+    let interpreter = Interpreter::new();
+    let mut resolver = Resolver::new();
+    resolver.run(&program).expect("checked programs resolve");
+
+    let mut linter = Linter { rules, warnings: Vec::new() };
+    linter.names(&resolver.into_scope_data(), &interpreter);
+    linter.stmts(&program.statements);
+
+    let mut warnings = linter.warnings;
+    warnings.sort_by_key(|w| w.span.start);
+    Ok(warnings)
+}
+
+/// One line per warning: `file:line:column: warning: message [rule]`.
+pub fn format_text(file: &str, source: &str, warnings: &[Warning]) -> Vec<String> {
+    let line_offsets = LineOffsets::new(source);
+    warnings
+        .iter()
+        .map(|w| {
+            format!(
+                "{}:{}:{}: warning: {} [{}]",
+                file,
+                line_offsets.line(w.span.start),
+                line_offsets.column(w.span.start),
+                w.message,
+                w.rule.name()
+            )
+        })
+        .collect()
+}
+
+/// One JSON object per warning, shaped like the diagnostics of `check::format_json`.
+pub fn format_json(file: &str, source: &str, warnings: &[Warning]) -> Vec<Value> {
+    let line_offsets = LineOffsets::new(source);
+    warnings
+        .iter()
+        .map(|w| {
+            json!({
+                "file": file,
+                "line": line_offsets.line(w.span.start),
+                "column": line_offsets.column(w.span.start),
+                "severity": "warning",
+                "rule": w.rule.name(),
+                "message": w.message,
+            })
+        })
+        .collect()
+}
+
+struct Linter {
+    rules: RuleSet,
+    warnings: Vec<Warning>,
+}
+
+impl Linter {
+    fn warn(&mut self, rule: Rule, span: Span, message: String) {
+        if self.rules.contains(rule) {
+            self.warnings.push(Warning { rule, span, message });
+        }
+    }
+
+    /// The rules about definitions and references. `interpreter` holds the native globals.
+    fn names(&mut self, data: &ScopeData, interpreter: &Interpreter) {
+        for (i, definition) in data.definitions.iter().enumerate() {
+            // A leading underscore marks a name as unused on purpose.
+            let read = definition.name.starts_with('_') || data.references_to(i).any(|r| !r.assignment);
+            match definition.kind {
+                _ if definition.global || read => {}
+                DefinitionKind::Parameter => self.warn(
+                    Rule::UnusedParameter,
+                    definition.span,
+                    format!("Parameter `{}` is never used", definition.name),
+                ),
+                DefinitionKind::Variable | DefinitionKind::Function(_) => self.warn(
+                    Rule::UnusedVariable,
+                    definition.span,
+                    format!("`{}` is never used", definition.name),
+                ),
+            }
+            if definition.shadows.is_some() {
+                self.warn(
+                    Rule::Shadowing,
+                    definition.span,
+                    format!("`{}` shadows a variable of an enclosing scope", definition.name),
+                );
+            }
+        }
+
+        for reference in &data.references {
+            if reference.assignment && reference.definition.is_none() && interpreter.globals().get(&reference.name).is_none() {
+                self.warn(
+                    Rule::UndeclaredGlobal,
+                    reference.span,
+                    format!("Assignment to undeclared variable `{}`", reference.name),
+                );
+            }
+        }
+    }
+
+    fn stmts(&mut self, stmts: &[WithSpan<Stmt>]) {
+        // Only the first unreachable statement is reported, the rest go with it.
+        if let Some(i) = stmts.iter().position(always_returns) {
+            if let Some(unreachable) = stmts.get(i + 1) {
+                self.warn(Rule::UnreachableCode, unreachable.span, "Unreachable code after `return`".to_string());
+            }
+        }
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &WithSpan<Stmt>) {
+        match &stmt.value {
+            Stmt::Expression(expr) | Stmt::Print(expr) | Stmt::Return(expr) | Stmt::Var(_, expr) => {
+                self.expr(stmt.span, expr)
+            }
+            Stmt::If(cond, then, else_) => {
+                self.expr(stmt.span, cond);
+                self.stmt(then);
+                if let Some(else_) = else_ {
+                    self.stmt(else_);
+                }
+            }
+            Stmt::While(cond, body) => {
+                self.expr(stmt.span, cond);
+                self.stmt(body);
+            }
+            Stmt::For(init, cond, incr, body) => {
+                if let Some(init) = init {
+                    self.stmt(init);
+                }
+                for expr in cond.iter().chain(incr) {
+                    self.expr(stmt.span, expr);
+                }
+                self.stmt(body);
+            }
+            Stmt::Block(stmts) => self.stmts(stmts),
+            Stmt::Function(name, _, body) => {
+                let mut returns = Vec::new();
+                collect_returns(body, &mut returns);
+                // `return;` can not be told apart from `return nil;`, both count as not returning a value.
+                let returns_value = returns.iter().any(|r| !matches!(r, Expr::Nil));
+                let returns_nothing = returns.iter().any(|r| matches!(r, Expr::Nil)) || !always_returns(body);
+                if returns_value && returns_nothing {
+                    self.warn(
+                        Rule::InconsistentReturn,
+                        name.span,
+                        format!("`{}` returns a value on some paths but not on others", name.value),
+                    );
+                }
+                self.stmt(body);
+            }
+        }
+    }
+
+    /// `span` is the statement of the expression, as expressions have no span of their own.
+    fn expr(&mut self, span: Span, expr: &Expr) {
+        match expr {
+            Expr::Binary(left, op, right) => {
+                let comparison = matches!(
+                    op,
+                    BinaryOp::Equals
+                        | BinaryOp::NotEquals
+                        | BinaryOp::Less
+                        | BinaryOp::LessOrEquals
+                        | BinaryOp::Greater
+                        | BinaryOp::GreaterOrEquals
+                );
+                let (same_left, same_right) = (ungrouped(left), ungrouped(right));
+                if comparison && is_pure(same_left) && same_left.print_structural() == same_right.print_structural() {
+                    self.warn(
+                        Rule::SelfComparison,
+                        first_span(left).unwrap_or(span),
+                        format!("`{}` is compared to itself", same_left.print_structural()),
+                    );
+                }
+                self.expr(span, left);
+                self.expr(span, right);
+            }
+            Expr::LogicalOr(left, _, right) | Expr::LogicalAnd(left, _, right) => {
+                self.expr(span, left);
+                self.expr(span, right);
+            }
+            Expr::Grouping(expr) | Expr::Unary(_, expr) | Expr::Assignment(_, _, expr) => self.expr(span, expr),
+            Expr::Call(callee, arguments) => {
+                self.expr(span, callee);
+                for argument in arguments {
+                    self.expr(span, argument);
+                }
+            }
+            Expr::Number(_) | Expr::String(_) | Expr::True | Expr::False | Expr::Nil | Expr::Variable(..) => {}
+        }
+    }
+}
+
+/// Whether running the statement always ends in a `return`.
+fn always_returns(stmt: &WithSpan<Stmt>) -> bool {
+    match &stmt.value {
+        Stmt::Return(_) => true,
+        Stmt::Block(stmts) => stmts.iter().any(always_returns),
+        Stmt::If(_, then, Some(else_)) => always_returns(then) && always_returns(else_),
+        _ => false,
+    }
+}
+
+/// The values returned by the statement, not looking into nested functions.
+fn collect_returns<'a>(stmt: &'a WithSpan<Stmt>, returns: &mut Vec<&'a Expr>) {
+    match &stmt.value {
+        Stmt::Return(expr) => returns.push(expr),
+        Stmt::Block(stmts) => stmts.iter().for_each(|stmt| collect_returns(stmt, returns)),
+        Stmt::If(_, then, else_) => {
+            collect_returns(then, returns);
+            if let Some(else_) = else_ {
+                collect_returns(else_, returns);
+            }
+        }
+        Stmt::While(_, body) | Stmt::For(_, _, _, body) => collect_returns(body, returns),
+        Stmt::Expression(_) | Stmt::Print(_) | Stmt::Var(_, _) | Stmt::Function(_, _, _) => {}
+    }
+}
+
+fn ungrouped(expr: &Expr) -> &Expr {
+    match expr {
+        Expr::Grouping(expr) => ungrouped(expr),
+        expr => expr,
+    }
+}
+
+/// Whether evaluating the expression twice gives the same value.
+fn is_pure(expr: &Expr) -> bool {
+    match expr {
+        Expr::Call(_, _) | Expr::Assignment(..) => false,
+        Expr::Grouping(expr) | Expr::Unary(_, expr) => is_pure(expr),
+        Expr::Binary(left, _, right) | Expr::LogicalOr(left, _, right) | Expr::LogicalAnd(left, _, right) => {
+            is_pure(left) && is_pure(right)
+        }
+        Expr::Number(_) | Expr::String(_) | Expr::True | Expr::False | Expr::Nil | Expr::Variable(..) => true,
+    }
+}
+
+/// The span of the first variable in the expression, the closest it has to a span of its own.
+fn first_span(expr: &Expr) -> Option<Span> {
+    match expr {
+        Expr::Variable(name, _) | Expr::Assignment(name, _, _) => Some(name.span),
+        Expr::Grouping(expr) | Expr::Unary(_, expr) => first_span(expr),
+        Expr::Binary(left, _, right) | Expr::LogicalOr(left, _, right) | Expr::LogicalAnd(left, _, right) => {
+            first_span(left).or_else(|| first_span(right))
+        }
+        Expr::Call(callee, arguments) => first_span(callee).or_else(|| arguments.iter().find_map(first_span)),
+        Expr::Number(_) | Expr::String(_) | Expr::True | Expr::False | Expr::Nil => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn warnings(source: &str) -> Vec<String> {
+        format_text("a.lux", source, &lint(source, RuleSet::all()).unwrap())
+    }
+
+    #[test]
+    fn test_unused_and_shadowing() {
+        let source = "\
+var a = 1;
+fun f(x, _y, z) {
+  var a = 2;
+  var b = 3;
+  b = 4;
+  return z;
+}
+f(1, 2, 3);
+";
+        assert_eq!(warnings(source), vec![
+            "a.lux:2:7: warning: Parameter `x` is never used [unused-parameter]",
+            "a.lux:3:7: warning: `a` is never used [unused-variable]",
+            "a.lux:3:7: warning: `a` shadows a variable of an enclosing scope [shadowing]",
+            "a.lux:4:7: warning: `b` is never used [unused-variable]",
+        ]);
+    }
+
+    #[test]
+    fn test_unreachable_code_and_returns() {
+        let source = "\
+fun f(n) {
+  if (n > 1) {
+    return n;
+    print n;
+  }
+}
+fun g(n) {
+  if (n) return 1; else return 2;
+  print n;
+}
+fun h(n) {
+  while (n) return n;
+  return;
+}
+";
+        assert_eq!(warnings(source), vec![
+            "a.lux:1:5: warning: `f` returns a value on some paths but not on others [inconsistent-return]",
+            "a.lux:4:5: warning: Unreachable code after `return` [unreachable-code]",
+            "a.lux:9:3: warning: Unreachable code after `return` [unreachable-code]",
+            "a.lux:11:5: warning: `h` returns a value on some paths but not on others [inconsistent-return]",
+        ]);
+    }
+
+    #[test]
+    fn test_undeclared_globals_and_self_comparison() {
+        let source = "\
+var a = 1;
+fun f() {
+  a = 2;
+  b = 3;
+  clock = nil;
+}
+print a == a;
+print f() == f();
+print (a + 1) < a + 1;
+";
+        assert_eq!(warnings(source), vec![
+            "a.lux:4:3: warning: Assignment to undeclared variable `b` [undeclared-global]",
+            "a.lux:7:7: warning: `a` is compared to itself [self-comparison]",
+            "a.lux:9:8: warning: `(a + 1)` is compared to itself [self-comparison]",
+        ]);
+    }
+
+    #[test]
+    fn test_rules_can_be_disabled() {
+        let source = "fun f(x) { var y = x == x; }";
+        let rules = RuleSet::all().without(Rule::UnusedVariable).without(Rule::SelfComparison);
+        let warnings = lint(source, rules).unwrap();
+        assert_eq!(warnings, vec![]);
+        assert_eq!(lint(source, RuleSet::none().with(Rule::UnusedVariable)).unwrap().len(), 1);
+        assert!(lint("print 1", RuleSet::all()).is_err());
+    }
+}
@@ -0,0 +1,495 @@
+//! Language server for lux, speaking the Language Server Protocol over stdio.
+//!
+//! Every open document is scanned, parsed and resolved again on each change.
+//! The resolver's `ScopeData` answers go-to-definition, find-references and
+//! hover, so the editor sees the same scoping rules as the interpreter.
+
+use std::collections::HashMap;
+use std::error::Error;
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{Completion, DocumentSymbolRequest, GotoDefinition, HoverRequest, References, Request as _};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    DiagnosticSeverity, DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse, GotoDefinitionParams,
+    GotoDefinitionResponse, Hover, HoverContents, HoverParams, HoverProviderCapability, Location, MarkupContent,
+    MarkupKind, OneOf, Position, PublishDiagnosticsParams, Range, ReferenceParams, ServerCapabilities,
+    SymbolKind, TextDocumentSyncCapability, TextDocumentSyncKind, Uri,
+};
+
+use crate::{
+    ast::Stmt,
+    check,
+    interpreter::{Interpreter, LuxValue},
+    position::{BytePos, Diagnostic, LineOffsets, Span},
+    program::Program,
+    resolver::{DefinitionKind, Name, Resolver, ScopeData},
+    scanner::Scanner,
+    token::KEYWORDS,
+};
+
+pub type LspError = Box<dyn Error + Send + Sync>;
+
+/// Serve a single client on stdin and stdout until it shuts the server down.
+pub fn run_stdio() -> Result<(), LspError> {
+    let (connection, io_threads) = Connection::stdio();
+    serve(&connection)?;
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+/// Answer requests on the connection until the client shuts the server down.
+pub fn serve(connection: &Connection) -> Result<(), LspError> {
+    connection.initialize(serde_json::to_value(capabilities())?)?;
+
+    let mut server = Server::default();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+                connection.sender.send(server.request(request).into())?;
+            }
+            Message::Notification(notification) => match server.notification(notification) {
+                Ok(Some(diagnostics)) => connection.sender.send(diagnostics.into())?,
+                Ok(None) => {}
+                // Notifications get no response, so a malformed one can only be logged.
+                Err(err) => eprintln!("Ignoring notification: {}", err),
+            },
+            Message::Response(_) => {}
+        }
+    }
+    Ok(())
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions::default()),
+        ..ServerCapabilities::default()
+    }
+}
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<Uri, Analysis>,
+}
+
+impl Server {
+    /// Handle a change to the open documents, returning the diagnostics to publish.
+    fn notification(&mut self, notification: Notification) -> Result<Option<Notification>, LspError> {
+        let (uri, analysis) = match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: lsp_types::DidOpenTextDocumentParams = serde_json::from_value(notification.params)?;
+                (params.text_document.uri, Some(Analysis::new(params.text_document.text)))
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: lsp_types::DidChangeTextDocumentParams = serde_json::from_value(notification.params)?;
+                // With full sync the last change holds the whole document.
+                match params.content_changes.into_iter().last() {
+                    Some(change) => (params.text_document.uri, Some(Analysis::new(change.text))),
+                    None => return Ok(None),
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: lsp_types::DidCloseTextDocumentParams = serde_json::from_value(notification.params)?;
+                (params.text_document.uri, None)
+            }
+            _ => return Ok(None),
+        };
+
+        let diagnostics = match analysis {
+            Some(analysis) => {
+                let diagnostics = analysis.lsp_diagnostics();
+                self.documents.insert(uri.clone(), analysis);
+                diagnostics
+            }
+            None => {
+                self.documents.remove(&uri);
+                vec![]
+            }
+        };
+        let params = PublishDiagnosticsParams { uri, diagnostics, version: None };
+        Ok(Some(Notification::new(PublishDiagnostics::METHOD.to_string(), params)))
+    }
+
+    fn request(&self, request: Request) -> Response {
+        let id = request.id.clone();
+        match self.answer(request) {
+            Ok(Some(response)) => response,
+            Ok(None) => Response::new_err(id, ErrorCode::MethodNotFound as i32, "Unsupported request".to_string()),
+            Err(err) => Response::new_err(id, ErrorCode::InvalidParams as i32, err.to_string()),
+        }
+    }
+
+    fn answer(&self, request: Request) -> Result<Option<Response>, LspError> {
+        let Request { id, method, params } = request;
+        let response = match method.as_str() {
+            GotoDefinition::METHOD => {
+                let params: GotoDefinitionParams = serde_json::from_value(params)?;
+                let document = params.text_document_position_params;
+                let result = self.document(&document.text_document.uri).and_then(|analysis| {
+                    let span = analysis.definition(analysis.offset(document.position))?;
+                    let location = Location::new(document.text_document.uri.clone(), analysis.range(span));
+                    Some(GotoDefinitionResponse::Scalar(location))
+                });
+                Response::new_ok(id, result)
+            }
+            References::METHOD => {
+                let params: ReferenceParams = serde_json::from_value(params)?;
+                let document = params.text_document_position;
+                let result = self.document(&document.text_document.uri).map(|analysis| {
+                    let pos = analysis.offset(document.position);
+                    analysis
+                        .references(pos, params.context.include_declaration)
+                        .into_iter()
+                        .map(|span| Location::new(document.text_document.uri.clone(), analysis.range(span)))
+                        .collect::<Vec<_>>()
+                });
+                Response::new_ok(id, result)
+            }
+            HoverRequest::METHOD => {
+                let params: HoverParams = serde_json::from_value(params)?;
+                let document = params.text_document_position_params;
+                let result = self.document(&document.text_document.uri).and_then(|analysis| {
+                    let (signature, span) = analysis.hover(analysis.offset(document.position))?;
+                    Some(Hover {
+                        contents: HoverContents::Markup(MarkupContent {
+                            kind: MarkupKind::Markdown,
+                            value: format!("```lux\n{}\n```", signature),
+                        }),
+                        range: Some(analysis.range(span)),
+                    })
+                });
+                Response::new_ok(id, result)
+            }
+            DocumentSymbolRequest::METHOD => {
+                let params: DocumentSymbolParams = serde_json::from_value(params)?;
+                let result = self
+                    .document(&params.text_document.uri)
+                    .map(|analysis| DocumentSymbolResponse::Nested(analysis.document_symbols()));
+                Response::new_ok(id, result)
+            }
+            Completion::METHOD => {
+                let params: CompletionParams = serde_json::from_value(params)?;
+                let result = self
+                    .document(&params.text_document_position.text_document.uri)
+                    .map(|analysis| CompletionResponse::Array(analysis.completions()));
+                Response::new_ok(id, result)
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(response))
+    }
+
+    fn document(&self, uri: &Uri) -> Option<&Analysis> {
+        self.documents.get(uri)
+    }
+}
+
+/// What the server knows about one version of a document.
+pub struct Analysis {
+    source: String,
+    line_offsets: LineOffsets,
+    diagnostics: Vec<Diagnostic>,
+    program: Option<Program>,
+    scopes: ScopeData,
+}
+
+impl Analysis {
+    pub fn new(source: String) -> Self {
+        let diagnostics = check::check(&source);
+        let (program, scopes) = match Program::parse(&Scanner::new(&source).run()) {
+            Ok(program) => {
+                let mut resolver = Resolver::new();
+                // The errors are already in the diagnostics.
+                let _ = resolver.run(&program);
+                let scopes = resolver.into_scope_data();
+                (Some(program), scopes)
+            }
+            Err(_) => (None, ScopeData::default()),
+        };
+
+        Analysis {
+            line_offsets: LineOffsets::new(&source),
+            source,
+            diagnostics,
+            program,
+            scopes,
+        }
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Where the name at `pos` is defined.
+    pub fn definition(&self, pos: BytePos) -> Option<Span> {
+        self.definition_index(pos).map(|i| self.scopes.definitions[i].span)
+    }
+
+    /// All uses of the name at `pos` in source order, optionally with its definition.
+    pub fn references(&self, pos: BytePos, include_definition: bool) -> Vec<Span> {
+        let Some(definition) = self.definition_index(pos) else {
+            return vec![];
+        };
+        let mut spans = Vec::new();
+        if include_definition {
+            spans.push(self.scopes.definitions[definition].span);
+        }
+        spans.extend(self.scopes.references_to(definition).map(|r| r.span));
+        spans.sort_by_key(|span| span.start);
+        spans
+    }
+
+    /// The signature of the name at `pos`, and the span of that name.
+    pub fn hover(&self, pos: BytePos) -> Option<(String, Span)> {
+        let (definition, span) = match self.scopes.at(pos)? {
+            Name::Definition(i) => (i, self.scopes.definitions[i].span),
+            Name::Reference(reference) => match reference.definition {
+                Some(i) => (i, reference.span),
+                None => {
+                    let native = natives().into_iter().find(|(name, _)| *name == reference.name)?;
+                    return Some((native.1.to_string(), reference.span));
+                }
+            },
+        };
+
+        let definition = &self.scopes.definitions[definition];
+        let signature = match &definition.kind {
+            DefinitionKind::Function(params) => format!("fun {}({})", definition.name, params.join(", ")),
+            DefinitionKind::Variable => format!("var {}", definition.name),
+            DefinitionKind::Parameter => format!("(parameter) {}", definition.name),
+        };
+        Some((signature, span))
+    }
+
+    /// The top level functions and variables.
+    pub fn document_symbols(&self) -> Vec<DocumentSymbol> {
+        let Some(program) = &self.program else {
+            return vec![];
+        };
+        program
+            .statements
+            .iter()
+            .filter_map(|stmt| {
+                let (name, kind, detail) = match &stmt.value {
+                    Stmt::Function(name, params, _) => {
+                        let params: Vec<&str> = params.iter().map(|p| &*p.value).collect();
+                        (name, SymbolKind::FUNCTION, Some(format!("({})", params.join(", "))))
+                    }
+                    Stmt::Var(name, _) => (name, SymbolKind::VARIABLE, None),
+                    _ => return None,
+                };
+                #[allow(deprecated)]
+                Some(DocumentSymbol {
+                    name: name.value.to_string(),
+                    detail,
+                    kind,
+                    tags: None,
+                    deprecated: None,
+                    range: self.range(stmt.span),
+                    selection_range: self.range(name.span),
+                    children: None,
+                })
+            })
+            .collect()
+    }
+
+    /// Keywords, the names defined in the document and the standard library.
+    pub fn completions(&self) -> Vec<CompletionItem> {
+        let keywords = KEYWORDS.iter().map(|k| (k.to_string(), CompletionItemKind::KEYWORD));
+        let definitions = self.scopes.definitions.iter().map(|d| {
+            let kind = match d.kind {
+                DefinitionKind::Function(_) => CompletionItemKind::FUNCTION,
+                _ => CompletionItemKind::VARIABLE,
+            };
+            (d.name.clone(), kind)
+        });
+        let natives = natives().into_iter().map(|(name, _)| (name, CompletionItemKind::FUNCTION));
+
+        let mut items: Vec<CompletionItem> = Vec::new();
+        for (label, kind) in keywords.chain(definitions).chain(natives) {
+            if !items.iter().any(|item| item.label == label) {
+                items.push(CompletionItem { label, kind: Some(kind), ..CompletionItem::default() });
+            }
+        }
+        items
+    }
+
+    fn definition_index(&self, pos: BytePos) -> Option<usize> {
+        match self.scopes.at(pos)? {
+            Name::Definition(i) => Some(i),
+            Name::Reference(reference) => reference.definition,
+        }
+    }
+
+    fn lsp_diagnostics(&self) -> Vec<lsp_types::Diagnostic> {
+        self.diagnostics
+            .iter()
+            .map(|d| lsp_types::Diagnostic {
+                range: self.range(d.span),
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some("rlux".to_string()),
+                message: d.message.clone(),
+                ..lsp_types::Diagnostic::default()
+            })
+            .collect()
+    }
+
+    /// LSP positions count lines from 0 and characters in UTF-16 code units.
+    fn position(&self, pos: BytePos) -> Position {
+        let line = self.line_offsets.line(pos);
+        let line_start = self.line_offsets.line_start(line).unwrap_or_default();
+        let character = self.source[line_start.0..pos.0].encode_utf16().count();
+        Position::new(line as u32 - 1, character as u32)
+    }
+
+    fn offset(&self, position: Position) -> BytePos {
+        let Some(line_start) = self.line_offsets.line_start(position.line as usize + 1) else {
+            return BytePos(self.source.len());
+        };
+        let mut character = 0;
+        for (i, c) in self.source[line_start.0..].char_indices() {
+            if character >= position.character as usize || c == '\n' {
+                return BytePos(line_start.0 + i);
+            }
+            character += c.len_utf16();
+        }
+        BytePos(self.source.len())
+    }
+
+    fn range(&self, span: Span) -> Range {
+        Range::new(self.position(span.start), self.position(span.end))
+    }
+}
+
+/// The globals every script starts with.
+fn natives() -> Vec<(String, LuxValue)> {
+    Interpreter::new().globals().variables()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_server::RequestId;
+    use lsp_types::notification::Initialized;
+    use lsp_types::{
+        DidOpenTextDocumentParams, InitializeParams, InitializedParams, PartialResultParams,
+        TextDocumentIdentifier, TextDocumentItem, TextDocumentPositionParams, WorkDoneProgressParams,
+    };
+    use std::thread;
+
+    const SOURCE: &str = "\
+var total = 0;
+fun add(a, b) {
+  var sum = a + b;
+  return sum;
+}
+total = add(total, 1);
+print total + sqrt(4);
+";
+
+    /// The offset of the `n`th occurrence of `needle` in `SOURCE`.
+    fn at(needle: &str, n: usize) -> BytePos {
+        BytePos(SOURCE.match_indices(needle).nth(n).unwrap().0)
+    }
+
+    fn text(span: Span) -> &'static str {
+        &SOURCE[span.start.0..span.end.0]
+    }
+
+    #[test]
+    fn test_definition_and_references() {
+        let analysis = Analysis::new(SOURCE.to_string());
+        assert!(analysis.diagnostics().is_empty());
+
+        let definition = analysis.definition(at("sum", 1)).unwrap();
+        assert_eq!(definition.start, at("sum", 0));
+        assert_eq!(text(definition), "sum");
+
+        let references = analysis.references(at("total", 0), true);
+        let starts: Vec<_> = references.iter().map(|s| s.start).collect();
+        assert_eq!(starts, vec![at("total", 0), at("total", 1), at("total", 2), at("total", 3)]);
+        assert_eq!(analysis.references(at("a + b", 0), false).len(), 1);
+    }
+
+    #[test]
+    fn test_hover_shows_signatures() {
+        let analysis = Analysis::new(SOURCE.to_string());
+        assert_eq!(analysis.hover(at("add", 1)).unwrap().0, "fun add(a, b)");
+        assert_eq!(analysis.hover(at("b", 1)).unwrap().0, "(parameter) b");
+        assert_eq!(analysis.hover(at("sqrt", 0)).unwrap().0, "<fun (native) sqrt>");
+        assert!(analysis.hover(at("print", 0)).is_none());
+    }
+
+    #[test]
+    fn test_symbols_and_completions() {
+        let analysis = Analysis::new(SOURCE.to_string());
+        let symbols: Vec<_> = analysis.document_symbols().into_iter().map(|s| (s.name, s.kind)).collect();
+        assert_eq!(symbols, vec![("total".to_string(), SymbolKind::VARIABLE), ("add".to_string(), SymbolKind::FUNCTION)]);
+
+        let labels: Vec<_> = analysis.completions().into_iter().map(|c| c.label).collect();
+        for label in ["while", "total", "add", "sum", "sqrt"] {
+            assert!(labels.contains(&label.to_string()), "missing {}", label);
+        }
+    }
+
+    #[test]
+    fn test_positions_count_utf16() {
+        let analysis = Analysis::new("var s = \"😀\"; s;\nprint s;".to_string());
+        let pos = BytePos("var s = \"😀\"; ".len());
+        assert_eq!(analysis.position(pos), Position::new(0, 14));
+        assert_eq!(analysis.offset(Position::new(0, 14)), pos);
+        assert_eq!(analysis.offset(Position::new(1, 6)), BytePos("var s = \"😀\"; s;\nprint ".len()));
+    }
+
+    #[test]
+    fn test_serves_client_over_connection() {
+        let (server, client) = Connection::memory();
+        let server = thread::spawn(move || serve(&server).unwrap());
+        let uri: Uri = "file:///main.lux".parse().unwrap();
+
+        #[allow(deprecated)]
+        let initialize = InitializeParams::default();
+        client.sender.send(Request::new(RequestId::from(1), "initialize".to_string(), initialize).into()).unwrap();
+        let Message::Response(response) = client.receiver.recv().unwrap() else { panic!("expected a response") };
+        assert!(response.result.unwrap()["capabilities"]["definitionProvider"].as_bool().unwrap());
+        client.sender.send(Notification::new(Initialized::METHOD.to_string(), InitializedParams {}).into()).unwrap();
+
+        // A malformed notification is skipped rather than stopping the server.
+        client.sender.send(Notification::new(DidOpenTextDocument::METHOD.to_string(), ()).into()).unwrap();
+        let open = DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(uri.clone(), "lux".to_string(), 1, "print a;\nvar a = 1;\na;".to_string()),
+        };
+        client.sender.send(Notification::new(DidOpenTextDocument::METHOD.to_string(), open).into()).unwrap();
+        let Message::Notification(published) = client.receiver.recv().unwrap() else { panic!("expected diagnostics") };
+        let published: PublishDiagnosticsParams = serde_json::from_value(published.params).unwrap();
+        assert!(published.diagnostics.is_empty());
+
+        let params = GotoDefinitionParams {
+            text_document_position_params: TextDocumentPositionParams::new(TextDocumentIdentifier::new(uri.clone()), Position::new(2, 0)),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+        client.sender.send(Request::new(RequestId::from(2), GotoDefinition::METHOD.to_string(), params).into()).unwrap();
+        let Message::Response(response) = client.receiver.recv().unwrap() else { panic!("expected a response") };
+        let location: Location = serde_json::from_value(response.result.unwrap()).unwrap();
+        assert_eq!(location.range, Range::new(Position::new(1, 4), Position::new(1, 5)));
+
+        client.sender.send(Request::new(RequestId::from(3), "shutdown".to_string(), ()).into()).unwrap();
+        client.receiver.recv().unwrap();
+        client.sender.send(Notification::new("exit".to_string(), ()).into()).unwrap();
+        server.join().unwrap();
+    }
+}
@@ -1,12 +1,31 @@
 use clap::Arg;
 use clap::ArgAction;
+use clap::ArgMatches;
 use clap::Command;
-use rlux::interpreter::Interpreter;
+use rlux::bench;
+use rlux::check;
+use rlux::dap;
+use rlux::debug;
+use rlux::dump;
+use rlux::formatter;
+use rlux::lint::{self, Rule, RuleSet};
+use rlux::interpreter::coverage::{self, FileCoverage};
+use rlux::interpreter::{Coverage, Interpreter, Profiler};
+use rlux::lsp;
+use rlux::program::Program;
+use rlux::repl::{self, helper::LuxHelper};
+use rlux::scanner::Scanner;
+use rlux::test_runner;
 use core::str;
+use std::env;
 use std::fs;
 use std::io::{self};
-use std::path::Path;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process;
 use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::Editor;
 
 fn main() {
     let matches = Command::new("rlux")
@@ -14,23 +33,204 @@ fn main() {
         .author("Author Name <frankhampusweslien@gmail.com>")
         .about("Does awesome things")
         .subcommand(
-            Command::new("run").about("Runs the application").arg(
+            Command::new("run")
+                .about("Runs the application")
+                .arg(
+                    Arg::new("filepath")
+                        .help("The path to the file to run")
+                        .action(ArgAction::Set)
+                        .value_name("FILE")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("profile")
+                        .help("Print the time spent in each function and the hits of each line")
+                        .long("profile")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("folded")
+                        .help("Write the profiled call stacks for flame graph tools, implies --profile")
+                        .long("folded")
+                        .value_name("FOLDED_FILE"),
+                )
+                .arg(coverage_arg())
+                .arg(lcov_arg()),
+        )
+        .subcommand(Command::new("repl").about("Starts a REPL"))
+        .subcommand(
+            Command::new("check")
+                .about("Scans, parses and resolves files without running them")
+                .arg(
+                    Arg::new("files")
+                        .help("The files to check")
+                        .action(ArgAction::Append)
+                        .value_name("FILES")
+                        .required(true)
+                        .num_args(1..),
+                )
+                .arg(
+                    Arg::new("format")
+                        .help("How to print the diagnostics")
+                        .long("format")
+                        .value_parser(["text", "json"])
+                        .default_value("text"),
+                ),
+        )
+        .subcommand(
+            Command::new("lint")
+                .about("Warns about code that is probably a mistake")
+                .arg(
+                    Arg::new("files")
+                        .help("The files to lint")
+                        .action(ArgAction::Append)
+                        .value_name("FILES")
+                        .required(true)
+                        .num_args(1..),
+                )
+                .arg(
+                    Arg::new("format")
+                        .help("How to print the warnings")
+                        .long("format")
+                        .value_parser(["text", "json"])
+                        .default_value("text"),
+                )
+                .arg(
+                    Arg::new("enable")
+                        .help("Only check the given rules")
+                        .long("enable")
+                        .value_name("RULE")
+                        .action(ArgAction::Append)
+                        .value_parser(Rule::ALL.map(Rule::name)),
+                )
+                .arg(
+                    Arg::new("disable")
+                        .help("Do not check the given rules")
+                        .long("disable")
+                        .value_name("RULE")
+                        .action(ArgAction::Append)
+                        .value_parser(Rule::ALL.map(Rule::name)),
+                ),
+        )
+        .subcommand(
+            Command::new("fmt")
+                .about("Formats files in place")
+                .arg(
+                    Arg::new("files")
+                        .help("The files to format")
+                        .action(ArgAction::Append)
+                        .value_name("FILES")
+                        .required(true)
+                        .num_args(1..),
+                )
+                .arg(
+                    Arg::new("check")
+                        .help("Only report files that are not formatted")
+                        .long("check")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("tokens").about("Prints the tokens of a file").arg(
                 Arg::new("filepath")
-                    .help("The path to the file to run")
+                    .help("The file to scan")
                     .action(ArgAction::Set)
                     .value_name("FILE")
                     .required(true)
                     .index(1),
             ),
         )
-        .subcommand(Command::new("repl").about("Starts a REPL"))
+        .subcommand(
+            Command::new("ast")
+                .about("Prints the syntax tree of a file")
+                .arg(
+                    Arg::new("filepath")
+                        .help("The file to parse")
+                        .action(ArgAction::Set)
+                        .value_name("FILE")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("json")
+                        .help("Print the tree as JSON")
+                        .long("json")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("test")
+                .about("Runs scripts against their `// expect:` comments and their `test_*` functions")
+                .arg(
+                    Arg::new("paths")
+                        .help("The scripts or directories of scripts to test")
+                        .action(ArgAction::Append)
+                        .value_name("PATHS")
+                        .required(true)
+                        .num_args(1..),
+                )
+                .arg(coverage_arg())
+                .arg(lcov_arg()),
+        )
+        .subcommand(
+            Command::new("bench")
+                .about("Times scripts over several runs and compares them against a saved baseline")
+                .arg(
+                    Arg::new("paths")
+                        .help("The scripts or directories of scripts to time")
+                        .action(ArgAction::Append)
+                        .value_name("PATHS")
+                        .required(true)
+                        .num_args(1..),
+                )
+                .arg(
+                    Arg::new("runs")
+                        .help("How many times to run each script")
+                        .long("runs")
+                        .short('n')
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("10"),
+                )
+                .arg(
+                    Arg::new("baseline")
+                        .help("Compare against the results saved in the file, failing on regressions")
+                        .long("baseline")
+                        .value_name("BASELINE_FILE"),
+                )
+                .arg(
+                    Arg::new("threshold")
+                        .help("How many percent slower than the baseline a script may get")
+                        .long("threshold")
+                        .value_parser(clap::value_parser!(f64))
+                        .default_value("10"),
+                )
+                .arg(
+                    Arg::new("save")
+                        .help("Save the results as a baseline")
+                        .long("save")
+                        .value_name("BASELINE_FILE"),
+                ),
+        )
+        .subcommand(
+            Command::new("debug").about("Runs a file in an interactive step debugger").arg(
+                Arg::new("filepath")
+                    .help("The file to debug")
+                    .action(ArgAction::Set)
+                    .value_name("FILE")
+                    .required(true)
+                    .index(1),
+            ),
+        )
+        .subcommand(Command::new("dap").about("Starts a debug adapter on stdin and stdout"))
+        .subcommand(Command::new("lsp").about("Starts a language server on stdin and stdout"))
         .get_matches();
 
     match matches.subcommand() {
         Some(("run", args)) => match args.get_one::<String>("filepath") {
             Some(filepath) => {
                 println!("Running with file: {}", filepath);
-                run_file(filepath).expect("Error running file");
+                run_file(filepath, args).expect("Error running file");
             }
             None => println!("No filepath was provided"),
         },
@@ -38,24 +238,158 @@ fn main() {
             println!("Starting REPL...");
             run_prompt();
         }
+        Some(("check", args)) => {
+            let files: Vec<&String> = args.get_many::<String>("files").unwrap_or_default().collect();
+            let json = args.get_one::<String>("format").map(|f| f.as_str()) == Some("json");
+            if !check_files(&files, json) {
+                process::exit(1);
+            }
+        }
+        Some(("lint", args)) => {
+            let files: Vec<&String> = args.get_many::<String>("files").unwrap_or_default().collect();
+            let json = args.get_one::<String>("format").map(|f| f.as_str()) == Some("json");
+            let rule = |name: &String| Rule::from_name(name).expect("clap only accepts rule names");
+            let mut rules = match args.get_many::<String>("enable") {
+                Some(enabled) => enabled.map(rule).fold(RuleSet::none(), RuleSet::with),
+                None => RuleSet::all(),
+            };
+            for disabled in args.get_many::<String>("disable").unwrap_or_default() {
+                rules = rules.without(rule(disabled));
+            }
+            if !lint_files(&files, rules, json) {
+                process::exit(1);
+            }
+        }
+        Some(("fmt", args)) => {
+            let files: Vec<&String> = args.get_many::<String>("files").unwrap_or_default().collect();
+            if !format_files(&files, args.get_flag("check")) {
+                process::exit(1);
+            }
+        }
+        Some(("tokens", args)) => {
+            let file = args.get_one::<String>("filepath").unwrap();
+            let source = read_source(file);
+            for line in dump::tokens(&source, &Scanner::new(&source).run()) {
+                println!("{}", line);
+            }
+        }
+        Some(("ast", args)) => {
+            let file = args.get_one::<String>("filepath").unwrap();
+            let source = read_source(file);
+            match Program::parse(&Scanner::new(&source).run()) {
+                Ok(program) if args.get_flag("json") => println!("{}", dump::json(&source, &program)),
+                Ok(program) => print!("{}", dump::tree(&source, &program)),
+                Err(diagnostics) => {
+                    for line in check::format_text(file, &source, &diagnostics) {
+                        eprintln!("{}", line);
+                    }
+                    process::exit(1);
+                }
+            }
+        }
+        Some(("test", args)) => {
+            let paths: Vec<&String> = args.get_many::<String>("paths").unwrap_or_default().collect();
+            let lcov = args.get_one::<String>("lcov");
+            let coverage = args.get_flag("coverage") || lcov.is_some();
+            let report = test_runner::run_paths(&paths, coverage).unwrap_or_else(|err| {
+                eprintln!("Could not read scripts: {}", err);
+                process::exit(1);
+            });
+            report.print(&mut io::stdout()).expect("Failed to write to stdout");
+            if coverage {
+                println!();
+                report_coverage(&report.coverage, &mut io::stdout(), lcov).expect("Failed to write coverage");
+            }
+            if report.failed() > 0 {
+                process::exit(1);
+            }
+        }
+        Some(("bench", args)) => {
+            if !bench_paths(args) {
+                process::exit(1);
+            }
+        }
+        Some(("debug", args)) => {
+            let file = args.get_one::<String>("filepath").unwrap();
+            let source = read_source(file);
+            let mut interpreter = Interpreter::new();
+            let debugger = debug::Cli::new(&source, io::stdin().lock(), io::stdout());
+            interpreter.set_debugger(Some(Box::new(debugger)));
+            println!("Type help for a list of commands.");
+            rlux::run(&source, &mut interpreter);
+        }
+        Some(("dap", _)) => {
+            if let Err(err) = dap::run_stdio() {
+                eprintln!("Debug adapter failed: {}", err);
+                process::exit(1);
+            }
+        }
+        Some(("lsp", _)) => {
+            if let Err(err) = lsp::run_stdio() {
+                eprintln!("Language server failed: {}", err);
+                process::exit(1);
+            }
+        }
         _ => println!("No valid subcommand was used"),
     }
 }
 
+/// Where the REPL keeps its history between sessions.
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| Path::new(&home).join(".rlux_history"))
+}
+
 fn run_prompt() {
     let mut interpreter = Interpreter::new();
-    let mut rl = rustyline::DefaultEditor::new().expect("Failed to create editor");
+    let mut rl: Editor<LuxHelper, DefaultHistory> = Editor::new().expect("Failed to create editor");
+    rl.set_helper(Some(LuxHelper::new(interpreter.globals().clone())));
+    let history = history_path();
+    if let Some(path) = &history {
+        // There is no history the first time the REPL is started.
+        let _ = rl.load_history(path);
+    }
+    let mut buffer = String::new();
 
+    println!("Type :help for a list of commands.");
     loop {
-        let readline = rl.readline("> ");
+        // Keep reading lines until the statement is complete. When the
+        // terminal supports it the helper already does this while editing.
+        let prompt = if buffer.is_empty() { "> " } else { ". " };
+        let readline = rl.readline(prompt);
         match readline {
             Ok(line) => {
-                let _ = rl.add_history_entry(line.as_str());
-                match rlux::run(line.trim(), &mut interpreter) {
-                    Some(v) => println!("{}", v.to_string()),
+                if buffer.is_empty() {
+                    if let Some(command) = repl::Command::parse(&line) {
+                        let _ = rl.add_history_entry(line.as_str());
+                        match command {
+                            Ok(command) => command
+                                .execute(&mut interpreter, &mut io::stdout())
+                                .expect("Failed to write to stdout"),
+                            Err(message) => eprintln!("{}", message),
+                        }
+                        if let Some(helper) = rl.helper_mut() {
+                            helper.set_globals(interpreter.globals().clone());
+                        }
+                        continue;
+                    }
+                } else {
+                    buffer.push('\n');
+                }
+                buffer.push_str(&line);
+                if repl::needs_more_input(&buffer) {
+                    continue;
+                }
+
+                let source = mem::take(&mut buffer);
+                let _ = rl.add_history_entry(source.as_str());
+                match rlux::run_repl(source.trim(), &mut interpreter) {
+                    Some(v) => println!("{:?}", v),
                     None => (),
                 }
             },
+            Err(ReadlineError::Interrupted) if !buffer.is_empty() => {
+                buffer.clear();
+            },
             Err(ReadlineError::Interrupted) => {
                 println!("CTRL-C");
                 break;
@@ -70,12 +404,232 @@ fn run_prompt() {
             }
         }
     }
+
+    if let Some(path) = &history {
+        if let Err(err) = rl.save_history(path) {
+            eprintln!("Could not save history to {}: {}", path.display(), err);
+        }
+    }
+}
+
+/// Print the diagnostics of all files and return true if there were none.
+fn check_files(files: &[&String], json: bool) -> bool {
+    let mut ok = true;
+    let mut reports = Vec::new();
+
+    for file in files {
+        let source = match fs::read_to_string(file) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("Could not read {}: {}", file, err);
+                ok = false;
+                continue;
+            }
+        };
+        let diagnostics = check::check(&source);
+        ok &= diagnostics.is_empty();
+        if json {
+            reports.extend(check::format_json(file, &source, &diagnostics));
+        } else {
+            for line in check::format_text(file, &source, &diagnostics) {
+                println!("{}", line);
+            }
+        }
+    }
+
+    if json {
+        println!("{}", serde_json::Value::Array(reports));
+    }
+    ok
+}
+
+/// Print the warnings of all files, or their errors if they do not check,
+/// and return true if there were none.
+fn lint_files(files: &[&String], rules: RuleSet, json: bool) -> bool {
+    let mut ok = true;
+    let mut reports = Vec::new();
+
+    for file in files {
+        let source = match fs::read_to_string(file) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("Could not read {}: {}", file, err);
+                ok = false;
+                continue;
+            }
+        };
+        let lines = match lint::lint(&source, rules) {
+            Ok(warnings) if json => {
+                reports.extend(lint::format_json(file, &source, &warnings));
+                ok &= warnings.is_empty();
+                continue;
+            }
+            Ok(warnings) => lint::format_text(file, &source, &warnings),
+            Err(errors) if json => {
+                reports.extend(check::format_json(file, &source, &errors));
+                ok = false;
+                continue;
+            }
+            Err(errors) => check::format_text(file, &source, &errors),
+        };
+        ok &= lines.is_empty();
+        for line in lines {
+            println!("{}", line);
+        }
+    }
+
+    if json {
+        println!("{}", serde_json::Value::Array(reports));
+    }
+    ok
 }
 
-fn run_file(path: &str) -> io::Result<()> {
+/// Time the scripts, save or compare against a baseline, and return false if
+/// a script failed or got slower than the baseline allows.
+fn bench_paths(args: &ArgMatches) -> bool {
+    let paths: Vec<&String> = args.get_many::<String>("paths").unwrap_or_default().collect();
+    let runs = *args.get_one::<usize>("runs").unwrap();
+    let baseline = args.get_one::<String>("baseline").map(|file| {
+        bench::from_json(&read_source(file)).unwrap_or_else(|err| {
+            eprintln!("Invalid baseline {}: {}", file, err);
+            process::exit(1);
+        })
+    });
+
+    let mut ok = true;
+    let mut benchmarks = Vec::new();
+    let scripts = test_runner::scripts(&paths).unwrap_or_else(|err| {
+        eprintln!("Could not read scripts: {}", err);
+        process::exit(1);
+    });
+    for script in scripts {
+        match bench::measure_file(&script, runs) {
+            Ok(Ok(benchmark)) => benchmarks.push(benchmark),
+            Ok(Err(err)) => {
+                eprintln!("{}: {}", script.display(), err);
+                ok = false;
+            }
+            Err(err) => {
+                eprintln!("Could not read {}: {}", script.display(), err);
+                ok = false;
+            }
+        }
+    }
+
+    bench::report(&benchmarks, baseline.as_deref(), &mut io::stdout()).expect("Failed to write to stdout");
+    if let Some(save) = args.get_one::<String>("save") {
+        if let Err(err) = fs::write(save, serde_json::to_string_pretty(&bench::to_json(&benchmarks)).unwrap()) {
+            eprintln!("Could not write {}: {}", save, err);
+            ok = false;
+        }
+    }
+    if let Some(baseline) = &baseline {
+        let threshold = *args.get_one::<f64>("threshold").unwrap();
+        for benchmark in bench::regressions(&benchmarks, baseline, threshold) {
+            eprintln!("{} is more than {}% slower than the baseline", benchmark.name, threshold);
+            ok = false;
+        }
+    }
+    ok
+}
+
+/// Format all files, or with `check` only list the ones that would change.
+/// Returns false if a file could not be formatted or, when checking, was not formatted.
+fn format_files(files: &[&String], check: bool) -> bool {
+    let mut ok = true;
+
+    for file in files {
+        let source = match fs::read_to_string(file) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("Could not read {}: {}", file, err);
+                ok = false;
+                continue;
+            }
+        };
+        let formatted = match formatter::format(&source) {
+            Ok(formatted) => formatted,
+            Err(diagnostics) => {
+                for line in check::format_text(file, &source, &diagnostics) {
+                    eprintln!("{}", line);
+                }
+                ok = false;
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+
+        if check {
+            println!("{} is not formatted", file);
+            ok = false;
+        } else if let Err(err) = fs::write(file, formatted) {
+            eprintln!("Could not write {}: {}", file, err);
+            ok = false;
+        }
+    }
+    ok
+}
+
+/// Read a file for the subcommands that only look at one file, exiting if it can not be read.
+fn read_source(file: &str) -> String {
+    fs::read_to_string(file).unwrap_or_else(|err| {
+        eprintln!("Could not read {}: {}", file, err);
+        process::exit(1);
+    })
+}
+
+fn coverage_arg() -> Arg {
+    Arg::new("coverage")
+        .help("Print which statements and branches ran")
+        .long("coverage")
+        .action(ArgAction::SetTrue)
+}
+
+fn lcov_arg() -> Arg {
+    Arg::new("lcov")
+        .help("Write the coverage as an lcov tracefile, implies --coverage")
+        .long("lcov")
+        .value_name("LCOV_FILE")
+}
+
+/// Print the coverage summary and optionally write the lcov tracefile.
+fn report_coverage<W: io::Write>(files: &[FileCoverage], out: &mut W, lcov: Option<&String>) -> io::Result<()> {
+    coverage::summary(files, out)?;
+    if let Some(lcov) = lcov {
+        coverage::write_lcov(files, &mut fs::File::create(lcov)?)?;
+    }
+    Ok(())
+}
+
+/// Run a file. Reports from profiling and coverage go to stderr, after the program's output.
+fn run_file(path: &str, args: &ArgMatches) -> io::Result<()> {
     let bytes = fs::read(Path::new(path))?;
     let content = str::from_utf8(&bytes).expect("Invalid UTF-8 sequence");
+    let folded = args.get_one::<String>("folded");
+    let lcov = args.get_one::<String>("lcov");
+
     let mut interpreter = Interpreter::new();
+    if args.get_flag("profile") || folded.is_some() {
+        interpreter.set_profiler(Some(Profiler::new()));
+    }
+    let coverage = (args.get_flag("coverage") || lcov.is_some()).then(Coverage::new);
+    interpreter.set_coverage(coverage.clone());
     rlux::run(content, &mut interpreter);
+
+    if let Some(profiler) = interpreter.take_profiler() {
+        let profile = profiler.finish();
+        profile.report(content, &mut io::stderr())?;
+        if let Some(folded) = folded {
+            profile.write_folded(&mut fs::File::create(folded)?)?;
+        }
+    }
+    if let Some(coverage) = coverage {
+        // A program that does not parse did not run, so it has no coverage.
+        if let Ok(program) = Program::parse(&Scanner::new(content).run()) {
+            report_coverage(&[coverage.file(path, content, &program)], &mut io::stderr(), lcov)?;
+        }
+    }
     Ok(())
 }
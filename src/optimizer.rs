@@ -0,0 +1,211 @@
+//! Constant folding: a pass over a parsed `Program` that computes what it can
+//! before the program runs.
+//!
+//! Operators on literals are replaced by their result, logical operators with
+//! a literal on the left are reduced to the side they evaluate to, and `if`
+//! statements with a literal condition are replaced by the branch that runs.
+//! Anything that would fail at run time, such as dividing by zero or adding a
+//! number to a string, is left alone so that it still fails where it is.
+
+use crate::{
+    ast::{BinaryOp, Expr, Stmt, UnaryOp},
+    interpreter::LuxValue,
+    position::WithSpan,
+    program::Program,
+};
+
+pub fn optimize(program: Program) -> Program {
+    Program { statements: statements(program.statements) }
+}
+
+fn statements(stmts: Vec<WithSpan<Stmt>>) -> Vec<WithSpan<Stmt>> {
+    stmts.into_iter().filter_map(statement).collect()
+}
+
+/// A statement that is always needed, such as the body of a loop.
+fn required(stmt: WithSpan<Stmt>) -> Box<WithSpan<Stmt>> {
+    let span = stmt.span;
+    Box::new(statement(stmt).unwrap_or_else(|| WithSpan::new(Stmt::Block(Vec::new()), span)))
+}
+
+/// The optimized statement, or `None` if it never does anything.
+fn statement(stmt: WithSpan<Stmt>) -> Option<WithSpan<Stmt>> {
+    let span = stmt.span;
+    let stmt = match stmt.value {
+        Stmt::Expression(expr) => Stmt::Expression(fold(expr)),
+        Stmt::Print(expr) => Stmt::Print(fold(expr)),
+        Stmt::Var(name, expr) => Stmt::Var(name, fold(expr)),
+        Stmt::Return(expr) => Stmt::Return(fold(expr)),
+        Stmt::Block(stmts) => Stmt::Block(statements(stmts)),
+        Stmt::Function(name, params, body) => Stmt::Function(name, params, required(*body)),
+        Stmt::If(cond, then, else_) => {
+            let cond = fold(cond);
+            match value(&cond) {
+                Some(value) if value.is_truthy() => return statement(*then),
+                Some(_) => return else_.and_then(|else_| statement(*else_)),
+                None => Stmt::If(cond, required(*then), else_.and_then(|else_| statement(*else_)).map(Box::new)),
+            }
+        }
+        Stmt::While(cond, body) => Stmt::While(fold(cond), required(*body)),
+        Stmt::For(init, cond, incr, body) => Stmt::For(
+            init.and_then(|init| statement(*init)).map(Box::new),
+            cond.map(fold),
+            incr.map(fold),
+            required(*body),
+        ),
+    };
+    Some(WithSpan::new(stmt, span))
+}
+
+/// Fold the constant parts of an expression.
+pub fn fold(expr: Expr) -> Expr {
+    match expr {
+        Expr::Grouping(inner) => {
+            let inner = fold(*inner);
+            if value(&inner).is_some() {
+                inner
+            } else {
+                Expr::grouping(inner)
+            }
+        }
+        Expr::Unary(op, operand) => {
+            let operand = fold(*operand);
+            match (&op, value(&operand)) {
+                (UnaryOp::Not, Some(value)) => literal(LuxValue::Boolean(!value.is_truthy())),
+                (UnaryOp::Negate, Some(LuxValue::Number(n))) => Expr::Number(-n),
+                _ => Expr::unary(op, operand),
+            }
+        }
+        Expr::Binary(left, op, right) => {
+            let left = fold(*left);
+            let right = fold(*right);
+            match (value(&left), value(&right)) {
+                (Some(l), Some(r)) => binary(l, &op, r).map_or_else(|| Expr::binary(left, op, right), literal),
+                _ => Expr::binary(left, op, right),
+            }
+        }
+        Expr::LogicalOr(left, operator, right) => {
+            let left = fold(*left);
+            match value(&left) {
+                Some(value) if value.is_truthy() => left,
+                Some(_) => fold(*right),
+                None => Expr::logical_or(left, operator, fold(*right)),
+            }
+        }
+        Expr::LogicalAnd(left, operator, right) => {
+            let left = fold(*left);
+            match value(&left) {
+                Some(value) if !value.is_truthy() => left,
+                Some(_) => fold(*right),
+                None => Expr::logical_and(left, operator, fold(*right)),
+            }
+        }
+        Expr::Assignment(name, slot, expr) => Expr::Assignment(name, slot, Box::new(fold(*expr))),
+        Expr::Call(callee, arguments) => Expr::call(fold(*callee), arguments.into_iter().map(fold).collect()),
+        expr @ (Expr::Number(_) | Expr::String(_) | Expr::True | Expr::False | Expr::Nil | Expr::Variable(..)) => expr,
+    }
+}
+
+/// The value of a literal.
+fn value(expr: &Expr) -> Option<LuxValue> {
+    match expr {
+        Expr::Number(n) => Some(LuxValue::Number(*n)),
+        Expr::String(s) => Some(LuxValue::String(s.clone())),
+        Expr::True => Some(LuxValue::Boolean(true)),
+        Expr::False => Some(LuxValue::Boolean(false)),
+        Expr::Nil => Some(LuxValue::Nil),
+        _ => None,
+    }
+}
+
+fn literal(value: LuxValue) -> Expr {
+    match value {
+        LuxValue::Number(n) => Expr::Number(n),
+        LuxValue::String(s) => Expr::String(s),
+        LuxValue::Boolean(true) => Expr::True,
+        LuxValue::Boolean(false) => Expr::False,
+        LuxValue::Nil => Expr::Nil,
+        LuxValue::Callable(_) => unreachable!("callables are never literals"),
+    }
+}
+
+/// What the interpreter computes for `left op right`, or `None` where it would fail.
+fn binary(left: LuxValue, op: &BinaryOp, right: LuxValue) -> Option<LuxValue> {
+    use LuxValue::{Boolean, Number, String};
+
+    let value = match (op, left, right) {
+        (BinaryOp::Equals, l, r) => Boolean(l.equals(&r)),
+        (BinaryOp::NotEquals, l, r) => Boolean(!l.equals(&r)),
+        (BinaryOp::Plus, Number(l), Number(r)) => Number(l + r),
+        (BinaryOp::Plus, String(l), String(r)) => String(format!("{}{}", l, r).into()),
+        (BinaryOp::Minus, Number(l), Number(r)) => Number(l - r),
+        (BinaryOp::Multiply, Number(l), Number(r)) => Number(l * r),
+        (BinaryOp::Divide, Number(l), Number(r)) if r != 0.0 => Number(l / r),
+        (BinaryOp::Less, Number(l), Number(r)) => Boolean(l < r),
+        (BinaryOp::Less, String(l), String(r)) => Boolean(l < r),
+        (BinaryOp::LessOrEquals, Number(l), Number(r)) => Boolean(l <= r),
+        (BinaryOp::LessOrEquals, String(l), String(r)) => Boolean(l <= r),
+        (BinaryOp::Greater, Number(l), Number(r)) => Boolean(l > r),
+        (BinaryOp::Greater, String(l), String(r)) => Boolean(l > r),
+        (BinaryOp::GreaterOrEquals, Number(l), Number(r)) => Boolean(l >= r),
+        (BinaryOp::GreaterOrEquals, String(l), String(r)) => Boolean(l >= r),
+        _ => return None,
+    };
+    Some(value)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ast::StructuralPrinter,
+        interpreter::{Interpreter, Output},
+        scanner::Scanner,
+    };
+
+    fn optimized(source: &str) -> Vec<String> {
+        let program = Program::parse(&Scanner::new(source).run()).unwrap();
+        optimize(program).statements.iter().map(|s| s.print_structural()).collect()
+    }
+
+    #[test]
+    fn test_folds_constants() {
+        assert_eq!(optimized("print 60 * 60 * 24;"), vec!["print 86400;"]);
+        assert_eq!(optimized("print \"a\" + \"b\" + x;"), optimized("print \"ab\" + x;"));
+        assert_eq!(optimized("print (1 + 2) < 4 == !nil;"), vec!["print true;"]);
+        assert_eq!(optimized("print -(2 - 3);"), vec!["print 1;"]);
+        assert_eq!(optimized("print \"a\" < \"b\";"), vec!["print true;"]);
+        assert_eq!(optimized("print 1 == \"1\";"), vec!["print false;"]);
+    }
+
+    #[test]
+    fn test_simplifies_logic() {
+        assert_eq!(optimized("print !true;"), vec!["print false;"]);
+        assert_eq!(optimized("print nil or x;"), optimized("print x;"));
+        assert_eq!(optimized("print 0 or x;"), vec!["print 0;"]);
+        assert_eq!(optimized("print false and f();"), vec!["print false;"]);
+        assert_eq!(optimized("print x or 1 + 1;"), optimized("print x or 2;"));
+    }
+
+    #[test]
+    fn test_removes_dead_branches() {
+        assert_eq!(optimized("if (false) print 1; print 2;"), vec!["print 2;"]);
+        assert_eq!(optimized("if (1 > 2) print 1; else print 3;"), vec!["print 3;"]);
+        assert_eq!(optimized("if (!nil) print 1; else print 3;"), vec!["print 1;"]);
+        assert_eq!(optimized("while (x) if (false) print 1;"), optimized("while (x) {}"));
+        assert_eq!(optimized("if (x) print 1; else if (false) print 2;"), optimized("if (x) print 1;"));
+    }
+
+    #[test]
+    fn test_keeps_runtime_errors() {
+        for source in ["print 1 / 0;", "print 1 + \"a\";", "print -\"a\";", "print nil < 1;", "print 2 * (3 / (1 - 1));"] {
+            let program = optimize(Program::parse(&Scanner::new(source).run()).unwrap());
+            let folded = program.statements[0].print_structural();
+            assert!(folded.contains(['/', '+', '-', '<']), "{} folded to {}", source, folded);
+
+            let mut interpreter = Interpreter::builder().output(Output::captured()).build();
+            assert!(interpreter.run(&program).is_err(), "{}", source);
+        }
+    }
+}
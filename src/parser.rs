@@ -1,4 +1,7 @@
+use std::rc::Rc;
+
 use crate::{
+    intern::Interner,
     position::*,
     token::{Token, TokenKind},
 };
@@ -9,6 +12,9 @@ pub struct Parser<'a> {
     current: usize,
     tokens: &'a Vec<WithSpan<Token>>,
     diagnostics: Vec<Diagnostic>,
+    unexpected_eof: bool,
+    trailing_expression: bool,
+    strings: Interner,
 }
 
 impl<'a> Parser<'a> {
@@ -17,9 +23,27 @@ impl<'a> Parser<'a> {
             current: 0,
             tokens: tokens,
             diagnostics: Vec::new(),
+            unexpected_eof: false,
+            trailing_expression: false,
+            strings: Interner::new(),
         }
     }
 
+    /// Allow the last statement to be an expression without a `;`, as in the REPL.
+    pub fn with_trailing_expression(mut self) -> Self {
+        self.trailing_expression = true;
+        self
+    }
+
+    pub fn allows_trailing_expression(&self) -> bool {
+        self.trailing_expression
+    }
+
+    /// The copy of `s` shared by the whole program being parsed.
+    pub fn intern(&mut self, s: &str) -> Rc<str> {
+        self.strings.intern(s)
+    }
+
     pub fn had_error(&self) -> bool {
         !self.diagnostics.is_empty()
     }
@@ -28,7 +52,23 @@ impl<'a> Parser<'a> {
         &self.diagnostics
     }
 
+    /// True if an error was reported after all tokens were consumed, which
+    /// means the input might be valid if more tokens were added.
+    pub fn unexpected_eof(&self) -> bool {
+        self.unexpected_eof
+    }
+
     pub fn error(&mut self, message: &str, span: Span) {
+        let mut span = span;
+        if self.is_at_end() {
+            self.unexpected_eof = true;
+            // The end of file token has no position, point just after the last token instead.
+            if span == EOF_TOKEN.span {
+                if let Some(last) = self.tokens.last() {
+                    span = Span { start: last.span.end, end: last.span.end };
+                }
+            }
+        }
         self.diagnostics.push(Diagnostic {
             message: message.to_string(),
             span,
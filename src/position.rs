@@ -1,4 +1,4 @@
-#[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Default)]
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Hash, Default)]
 pub struct BytePos(pub usize);
 
 impl BytePos {
@@ -116,6 +116,10 @@ pub struct LineOffsets {
 /// assert_eq!(offsets.line(BytePos(4)), 2);
 /// assert_eq!(offsets.line(BytePos(3)), 1);
 /// assert_eq!(offsets.line(BytePos(7)), 2);
+/// assert_eq!(offsets.column(BytePos(0)), 1);
+/// assert_eq!(offsets.column(BytePos(5)), 2);
+/// assert_eq!(offsets.line_start(2), Some(BytePos(4)));
+/// assert_eq!(offsets.line_start(3), None);
 /// ```
 impl LineOffsets {
     pub fn new(data: &str) -> Self {
@@ -142,4 +146,15 @@ impl LineOffsets {
             Err(line) => line,
         }
     }
+
+    /// Find the column for a given BytePos, counted in bytes from 1.
+    pub fn column(&self, pos: BytePos) -> usize {
+        let line_start = self.offsets[self.line(pos) - 1];
+        pos.0 - line_start + 1
+    }
+
+    /// Where a line, counted from 1, starts.
+    pub fn line_start(&self, line: usize) -> Option<BytePos> {
+        self.offsets.get(line.checked_sub(1)?).map(|offset| BytePos(*offset))
+    }
 }
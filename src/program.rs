@@ -4,12 +4,21 @@ use crate::{
 
 
 pub struct Program {
-    pub statements: Vec<Stmt>,
+    pub statements: Vec<WithSpan<Stmt>>,
 }
 
 impl Program {
     pub fn parse(tokens: &Vec<WithSpan<Token>>) -> Result<Self, Vec<Diagnostic>> {
-        let mut parser = Parser::new(tokens);
+        Self::parse_with(Parser::new(tokens))
+    }
+
+    /// Parse input typed in the REPL, where the last statement can be an
+    /// expression without a `;`.
+    pub fn parse_repl(tokens: &Vec<WithSpan<Token>>) -> Result<Self, Vec<Diagnostic>> {
+        Self::parse_with(Parser::new(tokens).with_trailing_expression())
+    }
+
+    fn parse_with(mut parser: Parser) -> Result<Self, Vec<Diagnostic>> {
         let mut statements = Vec::new();
 
         while !parser.is_at_end() {
@@ -30,4 +39,17 @@ impl Program {
 
         Ok(Self { statements })
     }
+
+    /// True if parsing in REPL mode fails only because the tokens end too
+    /// early, e.g. `fun f() {` or `print 1 +`.
+    pub fn is_incomplete(tokens: &Vec<WithSpan<Token>>) -> bool {
+        let mut parser = Parser::new(tokens).with_trailing_expression();
+
+        while !parser.is_at_end() {
+            if declaration(&mut parser).is_none() {
+                return parser.unexpected_eof();
+            }
+        }
+        false
+    }
 }
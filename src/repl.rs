@@ -0,0 +1,249 @@
+//! Helpers for the interactive prompt.
+
+pub mod helper;
+
+use std::fs;
+use std::io::{self, Write};
+use std::time::Instant;
+
+use crate::{
+    ast::StructuralPrinter, interpreter::Interpreter, position::LineOffsets, program::Program,
+    scanner::Scanner, token::Token,
+};
+
+const HELP: &str = "\
+Commands:
+  :tokens <source>  Show the tokens of the source
+  :ast <source>     Show the parsed source
+  :env              List the global variables and their values
+  :load <file>      Run a file in the current session
+  :reset            Start over with a fresh interpreter
+  :time <source>    Run the source and show how long it took
+  :help             Show this message";
+
+/// A command to inspect or control the REPL session, written as `:name argument`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command<'a> {
+    Tokens(&'a str),
+    Ast(&'a str),
+    Env,
+    Load(&'a str),
+    Reset,
+    Time(&'a str),
+    Help,
+}
+
+impl<'a> Command<'a> {
+    /// Parse a line of input as a command.
+    ///
+    /// Returns `None` if the line is not a command, and an error message if
+    /// it is an unknown command or is missing its argument.
+    pub fn parse(line: &'a str) -> Option<Result<Self, String>> {
+        let line = line.trim().strip_prefix(':')?;
+        let (name, argument) = match line.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (line, ""),
+        };
+
+        let with_argument = |command: fn(&'a str) -> Command<'a>, usage: &str| {
+            if argument.is_empty() {
+                Err(format!("Usage: :{} {}", name, usage))
+            } else {
+                Ok(command(argument))
+            }
+        };
+
+        Some(match name {
+            "tokens" => with_argument(Command::Tokens, "<source>"),
+            "ast" => with_argument(Command::Ast, "<source>"),
+            "env" => Ok(Command::Env),
+            "load" => with_argument(Command::Load, "<file>"),
+            "reset" => Ok(Command::Reset),
+            "time" => with_argument(Command::Time, "<source>"),
+            "help" => Ok(Command::Help),
+            _ => Err(format!("Unknown command `:{}`, try :help", name)),
+        })
+    }
+
+    pub fn execute<W: Write>(&self, interpreter: &mut Interpreter, out: &mut W) -> io::Result<()> {
+        match self {
+            Command::Tokens(source) => {
+                for token in Scanner::new(source).run() {
+                    writeln!(out, "{}..{} {:?}", token.span.start.0, token.span.end.0, token.value)?;
+                }
+            }
+            Command::Ast(source) => {
+                let tokens = Scanner::new(source).run();
+                match Program::parse(&tokens) {
+                    Ok(program) => {
+                        for stmt in &program.statements {
+                            writeln!(out, "{}", stmt.print_structural())?;
+                        }
+                    }
+                    Err(diagnostics) => {
+                        let line_offsets = LineOffsets::new(source);
+                        for diagnostic in diagnostics {
+                            writeln!(
+                                out,
+                                "Error: {} at line {}",
+                                diagnostic.message,
+                                line_offsets.line(diagnostic.span.start)
+                            )?;
+                        }
+                    }
+                }
+            }
+            Command::Env => {
+                for (name, value) in interpreter.globals().variables() {
+                    writeln!(out, "{} = {:?}", name, value)?;
+                }
+            }
+            Command::Load(path) => match fs::read_to_string(path) {
+                Ok(source) => {
+                    crate::run(&source, interpreter);
+                }
+                Err(err) => writeln!(out, "Could not read `{}`: {}", path, err)?,
+            },
+            Command::Reset => *interpreter = Interpreter::new(),
+            Command::Time(source) => {
+                let start = Instant::now();
+                let value = crate::run_repl(source, interpreter);
+                let elapsed = start.elapsed();
+                if let Some(value) = value {
+                    writeln!(out, "{:?}", value)?;
+                }
+                writeln!(out, "Took {:?}", elapsed)?;
+            }
+            Command::Help => writeln!(out, "{}", HELP)?,
+        }
+        Ok(())
+    }
+}
+
+/// Check if the source is the start of a statement that continues on the next line.
+///
+/// That is the case when a string, parenthesis or brace is left open, or the
+/// parser ran out of tokens in the middle of a statement.
+///
+/// ```
+/// use rlux::repl::is_incomplete;
+/// assert!(is_incomplete("fun f() {"));
+/// assert!(!is_incomplete("fun f() {}"));
+/// ```
+pub fn is_incomplete(source: &str) -> bool {
+    let tokens = Scanner::new(source).run();
+
+    let mut depth: i64 = 0;
+    for token in &tokens {
+        match token.value {
+            Token::LeftParen | Token::LeftBrace => depth += 1,
+            Token::RightParen | Token::RightBrace => depth -= 1,
+            Token::UnterminatedString => return true,
+            _ => {}
+        }
+    }
+
+    if depth != 0 {
+        // Too many closing brackets can never be fixed by adding more input.
+        return depth > 0;
+    }
+
+    Program::is_incomplete(&tokens)
+}
+
+
+/// Check if the REPL should keep reading lines before running the source.
+///
+/// A blank line submits incomplete input as is, so the user can always get
+/// back to the prompt (and see the parse error).
+pub fn needs_more_input(source: &str) -> bool {
+    let ends_with_blank_line = source.contains('\n')
+        && source.rsplit('\n').next().is_some_and(|line| line.trim().is_empty());
+    !ends_with_blank_line && !source.trim_start().starts_with(':') && is_incomplete(source)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_brackets_are_incomplete() {
+        assert!(is_incomplete("fun f() {"));
+        assert!(is_incomplete("print (1 +"));
+        assert!(is_incomplete("{ { print 1; }"));
+    }
+
+    #[test]
+    fn test_unterminated_string_is_incomplete() {
+        assert!(is_incomplete("print \"hello"));
+    }
+
+    #[test]
+    fn test_trailing_expression_is_complete() {
+        assert!(!is_incomplete("1 + 2"));
+        assert!(!is_incomplete("fun f() { return 1; }\nf()"));
+    }
+
+    #[test]
+    fn test_time_command_echoes_trailing_expression() {
+        let mut interpreter = Interpreter::new();
+        let out = execute(":time \"a\" + \"b\"", &mut interpreter);
+        assert!(out.starts_with("\"ab\"\nTook "));
+    }
+
+    #[test]
+    fn test_missing_end_of_statement_is_incomplete() {
+        assert!(is_incomplete("var a = 1"));
+        assert!(is_incomplete("print 1 +"));
+        assert!(is_incomplete("if (true)"));
+    }
+
+    fn execute(line: &str, interpreter: &mut Interpreter) -> String {
+        let mut out = Vec::new();
+        Command::parse(line).unwrap().unwrap().execute(interpreter, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(Command::parse("print 1;"), None);
+        assert_eq!(Command::parse(":env"), Some(Ok(Command::Env)));
+        assert_eq!(Command::parse(" :ast  1 + 2; "), Some(Ok(Command::Ast("1 + 2;"))));
+        assert!(matches!(Command::parse(":tokens"), Some(Err(_))));
+        assert!(matches!(Command::parse(":nope"), Some(Err(_))));
+    }
+
+    #[test]
+    fn test_tokens_and_ast_commands() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(execute(":tokens var a", &mut interpreter), "0..3 Var\n4..5 Identifier(\"a\")\n");
+        assert_eq!(execute(":ast print 1 + 2 * 3;", &mut interpreter), "print (1 + (2 * 3));\n");
+    }
+
+    #[test]
+    fn test_env_and_reset_commands() {
+        let mut interpreter = Interpreter::new();
+        crate::run("var greeting = \"hi\";", &mut interpreter);
+        assert!(execute(":env", &mut interpreter).contains("greeting = \"hi\"\n"));
+        execute(":reset", &mut interpreter);
+        assert!(!execute(":env", &mut interpreter).contains("greeting"));
+    }
+
+    #[test]
+    fn test_blank_line_submits_incomplete_input() {
+        assert!(needs_more_input("fun f() {"));
+        assert!(needs_more_input("fun f() {\n  print 1;"));
+        assert!(!needs_more_input("fun f() {\n  "));
+        assert!(!needs_more_input(":ast fun f() {"));
+    }
+
+    #[test]
+    fn test_complete_or_invalid_input_is_not_incomplete() {
+        assert!(!is_incomplete(""));
+        assert!(!is_incomplete("print 1;"));
+        assert!(!is_incomplete("fun f() {\n  print 1;\n}"));
+        assert!(!is_incomplete("print 1 2;"));
+        assert!(!is_incomplete("}"));
+    }
+}
@@ -0,0 +1,178 @@
+//! Line editing support for the REPL: completion, highlighting and validation.
+
+use std::borrow::Cow;
+
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Context, Helper};
+
+use crate::{
+    interpreter::Environment,
+    scanner::Scanner,
+    token::{Token, KEYWORDS},
+};
+
+const COMMANDS: [&str; 7] = [":tokens", ":ast", ":env", ":load", ":reset", ":time", ":help"];
+
+const KEYWORD: &str = "\x1b[35m";
+const STRING: &str = "\x1b[32m";
+const NUMBER: &str = "\x1b[33m";
+const ERROR: &str = "\x1b[31m";
+const COMMENT: &str = "\x1b[90m";
+const RESET: &str = "\x1b[0m";
+
+/// Rustyline helper that knows about lux and the globals of the session.
+pub struct LuxHelper {
+    globals: Environment,
+}
+
+impl LuxHelper {
+    pub fn new(globals: Environment) -> Self {
+        Self { globals }
+    }
+
+    /// Complete names from a new set of globals, e.g. after `:reset`.
+    pub fn set_globals(&mut self, globals: Environment) {
+        self.globals = globals;
+    }
+
+    /// Keywords, commands and global names starting with `prefix`.
+    pub fn candidates(&self, prefix: &str) -> Vec<String> {
+        let mut candidates: Vec<String> = if prefix.starts_with(':') {
+            COMMANDS.iter().map(|c| c.to_string()).collect()
+        } else {
+            KEYWORDS
+                .iter()
+                .map(|k| k.to_string())
+                .chain(self.globals.variables().into_iter().map(|(name, _)| name))
+                .collect()
+        };
+        candidates.retain(|c| c.starts_with(prefix));
+        candidates.sort();
+        candidates.dedup();
+        candidates
+    }
+}
+
+/// Add ANSI colors to lux source code.
+///
+/// Comments are not tokens, so anything the scanner skips that contains `//`
+/// is colored as a comment from there to the end of the line.
+pub fn highlight(source: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut last = 0;
+
+    for token in Scanner::new(source).run() {
+        let (start, end) = (token.span.start.0, token.span.end.0);
+        highlight_gap(&source[last..start], &mut out);
+
+        let color = match token.value {
+            Token::String(_) => Some(STRING),
+            Token::Number(_) => Some(NUMBER),
+            Token::UnterminatedString | Token::UnknownChar(_) => Some(ERROR),
+            Token::Identifier(_) => None,
+            ref token if KEYWORDS.contains(&token.to_string().as_str()) => Some(KEYWORD),
+            _ => None,
+        };
+        match color {
+            Some(color) => {
+                out.push_str(color);
+                out.push_str(&source[start..end]);
+                out.push_str(RESET);
+            }
+            None => out.push_str(&source[start..end]),
+        }
+        last = end;
+    }
+    highlight_gap(&source[last..], &mut out);
+    out
+}
+
+fn highlight_gap(gap: &str, out: &mut String) {
+    let mut rest = gap;
+    while let Some(start) = rest.find("//") {
+        let end = rest[start..].find('\n').map(|i| start + i).unwrap_or(rest.len());
+        out.push_str(&rest[..start]);
+        out.push_str(COMMENT);
+        out.push_str(&rest[start..end]);
+        out.push_str(RESET);
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+}
+
+impl Completer for LuxHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = line[..pos]
+            .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == ':'))
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let candidates = self
+            .candidates(&line[start..pos])
+            .into_iter()
+            .map(|c| Pair { display: c.clone(), replacement: c })
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Highlighter for LuxHelper {
+    fn highlight<'l>(&self, line: &'l str, _: usize) -> Cow<'l, str> {
+        if line.trim_start().starts_with(':') {
+            Cow::Borrowed(line)
+        } else {
+            Cow::Owned(highlight(line))
+        }
+    }
+
+    fn highlight_char(&self, _: &str, _: usize, _: bool) -> bool {
+        true
+    }
+}
+
+impl Validator for LuxHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        if super::needs_more_input(ctx.input()) {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
+        }
+    }
+}
+
+impl Hinter for LuxHelper {
+    type Hint = String;
+}
+
+impl Helper for LuxHelper {}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::LuxValue;
+
+    #[test]
+    fn test_candidates_include_keywords_and_globals() {
+        let mut globals = Environment::new();
+        globals.define("fib".to_string(), LuxValue::nil());
+        globals.define("foo".to_string(), LuxValue::nil());
+        let helper = LuxHelper::new(globals);
+
+        assert_eq!(helper.candidates("f"), vec!["false", "fib", "foo", "for", "fun"]);
+        assert_eq!(helper.candidates(":l"), vec![":load"]);
+    }
+
+    #[test]
+    fn test_highlight() {
+        assert_eq!(
+            highlight("var a = \"s\"; // c"),
+            format!("{KEYWORD}var{RESET} a = {STRING}\"s\"{RESET}; {COMMENT}// c{RESET}")
+        );
+        assert_eq!(highlight("1 / 2"), format!("{NUMBER}1{RESET} / {NUMBER}2{RESET}"));
+    }
+}
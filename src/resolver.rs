@@ -1,28 +1,112 @@
-use std::collections::HashMap;
-use crate::{ast::{Expr, Stmt}, interpreter::Interpreter, position::{Diagnostic, Span}, program::Program};
+use std::{collections::HashMap, rc::Rc};
+use crate::{ast::{Expr, Slot, Stmt}, position::{BytePos, Diagnostic, Span, WithSpan}, program::Program};
 
 
+/// What the resolver learned about the names in a program, for tools like the language server.
+#[derive(Debug, Clone, Default)]
+pub struct ScopeData {
+    pub definitions: Vec<Definition>,
+    pub references: Vec<Reference>,
+}
+
+impl ScopeData {
+    /// The definition or reference whose name contains `pos`.
+    pub fn at(&self, pos: BytePos) -> Option<Name<'_>> {
+        let contains = |span: Span| span.start <= pos && pos <= span.end;
+        if let Some(definition) = self.definitions.iter().position(|d| contains(d.span)) {
+            return Some(Name::Definition(definition));
+        }
+        self.references.iter().find(|r| contains(r.span)).map(Name::Reference)
+    }
+
+    /// All references that resolve to the definition.
+    pub fn references_to(&self, definition: usize) -> impl Iterator<Item = &Reference> {
+        self.references.iter().filter(move |r| r.definition == Some(definition))
+    }
+}
+
+/// A name found by `ScopeData::at`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Name<'a> {
+    /// Index into `ScopeData::definitions`.
+    Definition(usize),
+    Reference(&'a Reference),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub name: String,
+    /// Span of the name where it is declared.
+    pub span: Span,
+    pub kind: DefinitionKind,
+    /// Whether the definition is at the top level.
+    pub global: bool,
+    /// Index of the definition with the same name in an enclosing scope, if any.
+    pub shadows: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DefinitionKind {
+    Variable,
+    /// A function with its parameter names.
+    Function(Vec<String>),
+    Parameter,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub name: String,
+    pub span: Span,
+    /// Index into `ScopeData::definitions`, `None` for undefined and native globals.
+    pub definition: Option<usize>,
+    /// Whether the reference assigns to the variable instead of reading it.
+    pub assignment: bool,
+}
+
+/// A name in a scope, defined once its initializer has been resolved.
+#[derive(Debug, Clone, Copy)]
+struct Local {
+    defined: bool,
+    definition: usize,
+    /// Index of the variable in its scope when the program runs.
+    index: usize,
+}
+
+#[derive(Debug, Default)]
+struct Scope {
+    locals: HashMap<String, Local>,
+    /// The number of variables declared, counting redeclarations of a name.
+    len: usize,
+}
 
 
-/// Resolves all variables in a single pass
-pub struct Resolver<'i> {
-    interpreter: &'i mut Interpreter,
-    scopes: Vec<HashMap<String, bool>>,
+/// Resolves all variables in a single pass, recording in the program where
+/// each local variable is found when it runs.
+pub struct Resolver {
+    scopes: Vec<Scope>,
     diagnostics: Vec<Diagnostic>,
+    data: ScopeData,
 }
 
 
-impl<'i> Resolver<'i> {
-    pub fn new(interpreter: &'i mut Interpreter) -> Self {
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
         Self {
-            interpreter: interpreter,
             scopes: Vec::new(),
-            diagnostics: Vec::new()
+            diagnostics: Vec::new(),
+            data: ScopeData::default(),
         }
     }
 
     pub fn run(&mut self, program: &Program) -> Result<(), Vec<Diagnostic>> {
         self.resolve_stmts(&program.statements);
+        self.resolve_globals();
         if self.diagnostics.len() > 0 {
             return Err(self.diagnostics.clone())
         } else {
@@ -31,31 +115,61 @@ impl<'i> Resolver<'i> {
     }
 
 
-    fn resolve_stmts(&mut self, stmts: &Vec<Stmt>) {
+    /// The definitions and references found by `run`.
+    pub fn into_scope_data(self) -> ScopeData {
+        self.data
+    }
+
+    /// Globals are looked up when the code runs, so a reference may come
+    /// before the definition, as in mutually recursive functions.
+    fn resolve_globals(&mut self) {
+        let globals: HashMap<String, usize> = self
+            .data
+            .definitions
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, d)| d.global)
+            .map(|(i, d)| (d.name.clone(), i))
+            .collect();
+        for reference in &mut self.data.references {
+            if reference.definition.is_none() {
+                reference.definition = globals.get(reference.name.as_str()).copied();
+            }
+        }
+        for definition in &mut self.data.definitions {
+            if !definition.global && definition.shadows.is_none() {
+                definition.shadows = globals.get(definition.name.as_str()).copied();
+            }
+        }
+    }
+
+    fn resolve_stmts(&mut self, stmts: &Vec<WithSpan<Stmt>>) {
         for stmt in stmts {
             self.resolve_stmt(stmt);
         }
     }
 
-    fn resolve_stmt(&mut self, stmt: &Stmt) {
-        match stmt {
+    fn resolve_stmt(&mut self, stmt: &WithSpan<Stmt>) {
+        match &stmt.value {
             Stmt::Block(stmts) => {
                 self.scoped(|this| {
                     this.resolve_stmts(stmts);
                 });
             }
             Stmt::Var(id, expr) => {
-                self.declare(id);
+                self.declare(id, DefinitionKind::Variable);
                 self.resolve_expr(expr);
-                self.define(id);
+                self.define(&id.value);
             }
             Stmt::Function(name, vars, stmts) => {
-                self.declare(name); //TODO: remove this line
-                self.define(name);
+                let params = vars.iter().map(|v| v.value.to_string()).collect();
+                self.declare(name, DefinitionKind::Function(params));
+                self.define(&name.value);
                 self.scoped(|this| {
                     for var in vars {
-                        this.declare(var); //TODO: remove this line
-                        this.define(var);
+                        this.declare(var, DefinitionKind::Parameter);
+                        this.define(&var.value);
                     }
                     this.resolve_stmt(stmts);
                 });
@@ -74,6 +188,20 @@ impl<'i> Resolver<'i> {
                 self.resolve_expr(cond);
                 self.resolve_stmt(body);
             }
+            Stmt::For(init, cond, incr, body) => {
+                self.scoped(|this| {
+                    if let Some(init) = init {
+                        this.resolve_stmt(init);
+                    }
+                    if let Some(cond) = cond {
+                        this.resolve_expr(cond);
+                    }
+                    this.resolve_stmt(body);
+                    if let Some(incr) = incr {
+                        this.resolve_expr(incr);
+                    }
+                });
+            }
         }
     }
 
@@ -85,26 +213,26 @@ impl<'i> Resolver<'i> {
                     self.resolve_expr(expr);
                 }
             },
-            Expr::Variable(id) => {
+            Expr::Variable(id, slot) => {
                 if let Some(scope) = self.scopes.last_mut() {
-                    if scope.get(id) == Some(&false) {
+                    if scope.locals.get(&id.value).is_some_and(|local| !local.defined) {
                         self.diagnostics.push(Diagnostic {
-                            span: Span::empty(),
-                            message: format!("Can't read local variable '{}' in its own initializer.", id)
+                            span: id.span,
+                            message: format!("Can't read local variable '{}' in its own initializer.", id.value)
                         });
                     }
                 }
-                self.resolve_local(id);
+                self.resolve_local(id, slot, false);
             }
-            Expr::Assignment(id, inner_expr) => {
+            Expr::Assignment(id, slot, inner_expr) => {
                 self.resolve_expr(inner_expr);
-                self.resolve_local(id);
+                self.resolve_local(id, slot, true);
             }
-            Expr::LogicalOr(left, right) => {
+            Expr::LogicalOr(left, _, right) => {
                 self.resolve_expr(left);
                 self.resolve_expr(right);
             }
-            Expr::LogicalAnd(left, right) => {
+            Expr::LogicalAnd(left, _, right) => {
                 self.resolve_expr(left);
                 self.resolve_expr(right);
             }
@@ -119,16 +247,24 @@ impl<'i> Resolver<'i> {
     }
 
 
-    fn resolve_local(&mut self, id: &str) {
+    fn resolve_local(&mut self, id: &WithSpan<String>, slot: &Slot, assignment: bool) {
+        let mut definition = None;
         let len = self.scopes.len();
         for depth in 0..len {
             let i = len - depth - 1;
             let scope = &self.scopes[i];
-            if scope.contains_key(id) {
-                self.interpreter.resolve_local(id, depth);
-                return
+            if let Some(local) = scope.locals.get(&id.value) {
+                slot.set(depth, local.index);
+                definition = Some(local.definition);
+                break;
             }
         }
+        self.data.references.push(Reference {
+            name: id.value.to_string(),
+            span: id.span,
+            definition,
+            assignment,
+        });
     }
 
     fn scoped<I>(&mut self, inner: I)
@@ -144,7 +280,7 @@ impl<'i> Resolver<'i> {
     /// One should ideally use `scoped`. Callers of `begin_scope` must also call `end_scope`.
     #[inline]
     fn begin_scope(&mut self) {
-        self.scopes.push(HashMap::new());
+        self.scopes.push(Scope::default());
     }
 
     #[inline]
@@ -152,15 +288,26 @@ impl<'i> Resolver<'i> {
         self.scopes.pop();
     }
 
-    fn declare(&mut self, id: &str) {
+    fn declare(&mut self, id: &WithSpan<Rc<str>>, kind: DefinitionKind) {
+        let definition = self.data.definitions.len();
+        let enclosing = self.scopes.len().saturating_sub(1);
+        let shadows = self.scopes[..enclosing].iter().rev().find_map(|scope| scope.locals.get(&*id.value));
+        self.data.definitions.push(Definition {
+            name: id.value.to_string(),
+            span: id.span,
+            kind,
+            global: self.scopes.is_empty(),
+            shadows: shadows.map(|local| local.definition),
+        });
         if let Some(scope) = self.scopes.last_mut() {
-            scope.insert(id.to_string(), false);
+            scope.locals.insert(id.value.to_string(), Local { defined: false, definition, index: scope.len });
+            scope.len += 1;
         }
     }
 
     fn define(&mut self, id: &str) {
-        if let Some(scope) = self.scopes.last_mut() {
-            scope.insert(id.to_string(), true);
+        if let Some(local) = self.scopes.last_mut().and_then(|scope| scope.locals.get_mut(id)) {
+            local.defined = true;
         }
     }
 
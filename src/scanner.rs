@@ -8,6 +8,7 @@ pub struct Scanner<'a> {
     current: BytePos,
     source: &'a str,
     it: Peekable<Chars<'a>>,
+    comments: Vec<WithSpan<String>>,
 }
 
 impl<'a> Scanner<'a> {
@@ -17,9 +18,17 @@ impl<'a> Scanner<'a> {
             start: BytePos::default(),
             source: buf,
             it: buf.chars().peekable(),
+            comments: Vec::new(),
         }
     }
 
+    /// The comments skipped by `run`, including the leading `//`.
+    ///
+    /// Comments are not tokens, but tools like the formatter need to keep them.
+    pub fn comments(&self) -> &[WithSpan<String>] {
+        &self.comments
+    }
+
     fn next(&mut self) -> Option<char> {
         let next = self.it.next();
         if let Some(c) = next {
@@ -104,6 +113,8 @@ impl<'a> Scanner<'a> {
                     while self.peek() != Some(&'\n') && self.peek().is_some() {
                         self.next();
                     }
+                    let comment = self.source[self.start.0..self.current.0].to_string();
+                    self.comments.push(self.with_span(comment));
                     None
                 } else {
                     Some(Token::Slash)
@@ -153,8 +164,8 @@ impl<'a> Scanner<'a> {
         }
     }
 
-    fn with_span(&self, token_type: Token) -> WithSpan<Token> {
-        WithSpan::new_unchecked(token_type, self.start.0, self.current.0)
+    fn with_span<T>(&self, value: T) -> WithSpan<T> {
+        WithSpan::new_unchecked(value, self.start.0, self.current.0)
     }
 
     fn string(&mut self) -> Token {
@@ -271,6 +282,7 @@ mod tests {
         let tokens = scanner.run();
         let expected = vec![WithSpan::new_unchecked(Token::BangEqual, 0, 2)];
         assert_eq!(tokens, expected);
+        assert_eq!(scanner.comments(), &[WithSpan::new_unchecked("// == <= >=".to_string(), 3, 14)]);
     }
 
     #[test]
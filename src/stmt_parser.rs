@@ -1,9 +1,24 @@
 use crate::{
- ast::{Expr, Stmt}, expr_parser::expression, parser::Parser, token::{Token, TokenKind}
+ ast::{Expr, Stmt}, expr_parser::expression, parser::Parser, position::{Span, WithSpan}, token::{Token, TokenKind}
 };
 
 
-pub fn declaration(p: &mut Parser) -> Option<Stmt> {
+/// Parse with `rule` and attach the span from the first to the last token consumed.
+fn spanned<F>(p: &mut Parser, rule: F) -> Option<WithSpan<Stmt>>
+where
+    F: FnOnce(&mut Parser) -> Option<Stmt>,
+{
+    let start = p.peek_token().span.start;
+    let stmt = rule(p)?;
+    let end = p.previous().span.end;
+    Some(WithSpan::new(stmt, Span { start, end }))
+}
+
+pub fn declaration(p: &mut Parser) -> Option<WithSpan<Stmt>> {
+    spanned(p, declaration_kind)
+}
+
+fn declaration_kind(p: &mut Parser) -> Option<Stmt> {
     if p.is(TokenKind::Fun){
         return function(p);
     } else if p.is(TokenKind::Var) {
@@ -16,11 +31,11 @@ pub fn declaration(p: &mut Parser) -> Option<Stmt> {
         };
         p.expect(TokenKind::Semicolon)?;
         match &name.value {
-            Token::Identifier(name) => return Some(Stmt::Var(name.clone(), expr)),
+            Token::Identifier(id) => return Some(Stmt::Var(WithSpan::new(p.intern(id), name.span), expr)),
             _ => panic!("Expected identifier"),
         }
     }
-    statement(p)
+    statement_kind(p)
 }
 
 fn function(p: &mut Parser) -> Option<Stmt> {
@@ -30,8 +45,8 @@ fn function(p: &mut Parser) -> Option<Stmt> {
     let name = p.expect(TokenKind::Identifier)?;
 
 
-    let name = if let Token::Identifier(id) = name.value.clone() {
-        id
+    let name = if let Token::Identifier(id) = &name.value {
+        WithSpan::new(p.intern(id), name.span)
     } else {
         panic!("Expected an indentifer but it wasn't")
     };
@@ -46,8 +61,8 @@ fn function(p: &mut Parser) -> Option<Stmt> {
             }
             let par = p.expect(TokenKind::Identifier)?;
 
-            if let Token::Identifier(id) = par.value.clone() {
-                parameters.push(id);
+            if let Token::Identifier(id) = &par.value {
+                parameters.push(WithSpan::new(p.intern(id), par.span));
             } else {
                 panic!("Expected an indentifer but it wasn't")
             }
@@ -61,14 +76,18 @@ fn function(p: &mut Parser) -> Option<Stmt> {
 
     // body
 
-    let body = block(p)?;
+    let body = spanned(p, block)?;
 
     Some(Stmt::Function(name, parameters, Box::new(body)))
 }
 
 
 
-fn statement(p: &mut Parser) -> Option<Stmt> {
+fn statement(p: &mut Parser) -> Option<WithSpan<Stmt>> {
+    spanned(p, statement_kind)
+}
+
+fn statement_kind(p: &mut Parser) -> Option<Stmt> {
     if p.check(TokenKind::For) {
         return for_statement(p);
     } else if p.check(TokenKind::If) {
@@ -91,6 +110,9 @@ fn statement(p: &mut Parser) -> Option<Stmt> {
         return block(p);
     } else {
         let expr = expression(p)?;
+        if p.allows_trailing_expression() && p.is_at_end() {
+            return Some(Stmt::Expression(expr));
+        }
         p.expect(TokenKind::Semicolon)?;
         return Some(Stmt::Expression(expr));
     }
@@ -103,20 +125,22 @@ fn for_statement(p: &mut Parser) -> Option<Stmt> {
     p.expect(TokenKind::For)?;
     p.expect(TokenKind::LeftParen)?;
 
-    let initializer: Option<Stmt> = if p.is(TokenKind::Semicolon) {
+    let initializer: Option<WithSpan<Stmt>> = if p.is(TokenKind::Semicolon) {
         None
     } else if p.check(TokenKind::Var) {
         Some(declaration(p)?)
     } else {
-        let expr = expression(p)?;
-        p.expect(TokenKind::Semicolon)?;
-        Some(Stmt::Expression(expr))
+        Some(spanned(p, |p| {
+            let expr = expression(p)?;
+            p.expect(TokenKind::Semicolon)?;
+            Some(Stmt::Expression(expr))
+        })?)
     };
 
     let condition = if p.check(TokenKind::Semicolon) {
-        Expr::true_expr()
+        None
     } else {
-        expression(p)?
+        Some(expression(p)?)
     };
 
     p.expect(TokenKind::Semicolon)?;
@@ -130,22 +154,7 @@ fn for_statement(p: &mut Parser) -> Option<Stmt> {
     p.expect(TokenKind::RightParen)?;
     let body = statement(p)?;
 
-    // Construct the for loop as a while loop
-    let while_body = if let Some(inc) = increment {
-        Stmt::Block(vec![body, Stmt::Expression(inc)])
-    } else {
-        body
-    };
-
-    let while_stmt = Stmt::While(condition, Box::new(while_body));
-
-    let for_stmt = if let Some(init) = initializer {
-        Stmt::Block(vec![init, while_stmt])
-    } else {
-        while_stmt
-    };
-
-    Some(for_stmt)
+    Some(Stmt::For(initializer.map(Box::new), condition, increment, Box::new(body)))
 }
 
 fn while_statement(p: &mut Parser) -> Option<Stmt> {
@@ -173,7 +182,7 @@ fn if_statement(p: &mut Parser) -> Option<Stmt> {
 
 
 fn block(p: &mut Parser) -> Option<Stmt> {
-    let mut stmts = Vec::new();
+    let mut stmts: Vec<WithSpan<Stmt>> = Vec::new();
     p.expect(TokenKind::LeftBrace)?;
     while !p.check(TokenKind::RightBrace) && !p.is_at_end() {
         let stmt = declaration(p)?;
@@ -191,10 +200,6 @@ pub fn drop_until_statement(p: &mut Parser) {
     while !p.is_at_end() && !p.is(TokenKind::Semicolon) {
         p.advance();
     }
-
-    if !p.is_at_end() {
-        p.expect(TokenKind::Semicolon);
-    }
 }
 
 
@@ -208,13 +213,13 @@ mod tests {
         WithSpan::new_unchecked(kind, 0, 1)
     }
 
-    /// Parse the given tokens into an expression.
+    /// Parse the given tokens into a statement.
     fn run_test(tokens: &Vec<Token>) -> Result<Stmt, Vec<Diagnostic>> {
         let tokens: Vec<WithSpan<Token>> = tokens.into_iter().map(|t| token(t.clone())).collect();
         let mut parser = Parser::new(&tokens);
 
         match statement(&mut parser) {
-            Some(expr) => Ok(expr),
+            Some(stmt) => Ok(stmt.value),
             None => Err(Vec::from_iter(
                 parser.diagnostics().iter().map(|d| d.clone()),
             )),
@@ -244,6 +249,20 @@ mod tests {
     }
 
 
+    #[test]
+    fn test_can_parse_trailing_expression_when_allowed() {
+        let tokens = vec![token(Token::Number(1.0))];
+        let mut parser = Parser::new(&tokens).with_trailing_expression();
+        assert_eq!(statement(&mut parser).map(|s| s.value), Some(Stmt::Expression(Expr::Number(1.0))));
+    }
+
+    #[test]
+    fn test_trailing_expression_must_be_last() {
+        let tokens = vec![token(Token::Number(1.0)), token(Token::Number(2.0))];
+        let mut parser = Parser::new(&tokens).with_trailing_expression();
+        assert_eq!(statement(&mut parser).map(|s| s.value), None);
+    }
+
     #[test]
     fn test_can_parse_empty_block() {
         let tokens = vec![Token::LeftBrace, Token::RightBrace];
@@ -255,14 +274,68 @@ mod tests {
     fn test_can_parse_block_with_statements() {
         let tokens = vec![Token::LeftBrace, Token::Number(1.0), Token::Semicolon, Token::RightBrace];
         let stmt = run_test(&tokens);
-        assert_eq!(stmt, Ok(Stmt::Block(vec![Stmt::Expression(Expr::Number(1.0))])));
+        assert_eq!(stmt, Ok(Stmt::Block(vec![WithSpan::new_unchecked(Stmt::Expression(Expr::Number(1.0)), 0, 1)])));
     }
 
     #[test]
     fn test_can_parse_block_with_nested_blocks() {
         let tokens = vec![Token::LeftBrace, Token::LeftBrace, Token::RightBrace, Token::RightBrace];
         let stmt = run_test(&tokens);
-        assert_eq!(stmt, Ok(Stmt::Block(vec![Stmt::Block(Vec::new())])));
+        assert_eq!(stmt, Ok(Stmt::Block(vec![WithSpan::new_unchecked(Stmt::Block(Vec::new()), 0, 1)])));
+    }
+
+    #[test]
+    fn test_statements_span_their_tokens() {
+        let tokens = crate::scanner::Scanner::new("if (a)\n  print a;").run();
+        let mut parser = Parser::new(&tokens);
+        let stmt = statement(&mut parser).unwrap();
+        assert_eq!(stmt.span, Span { start: crate::position::BytePos(0), end: crate::position::BytePos(17) });
+        match stmt.value {
+            Stmt::If(_, then, None) => assert_eq!(then.span.start.0, 9),
+            other => panic!("Expected an if statement, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_recovery_resumes_after_the_semicolon() {
+        let tokens = crate::scanner::Scanner::new("print ; print 1;").run();
+        let mut parser = Parser::new(&tokens);
+        assert_eq!(declaration(&mut parser), None);
+        drop_until_statement(&mut parser);
+        assert_eq!(declaration(&mut parser).map(|s| s.value), Some(Stmt::Print(Expr::Number(1.0))));
+        assert_eq!(parser.diagnostics().len(), 1);
+    }
+
+    #[test]
+    fn test_can_parse_for_statement() {
+        let tokens = crate::scanner::Scanner::new("for (var i = 0; i < 3; i = i + 1) print i;").run();
+        let mut parser = Parser::new(&tokens);
+        match statement(&mut parser).unwrap().value {
+            Stmt::For(Some(init), Some(Expr::Binary(..)), Some(Expr::Assignment(..)), body) => {
+                assert!(matches!(init.value, Stmt::Var(..)));
+                assert!(matches!(body.value, Stmt::Print(_)));
+            }
+            other => panic!("Expected a for statement, got {:?}", other),
+        }
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_can_parse_for_statement_without_clauses() {
+        let tokens = crate::scanner::Scanner::new("for (;;) {}").run();
+        let mut parser = Parser::new(&tokens);
+        let stmt = statement(&mut parser).unwrap().value;
+        assert!(matches!(stmt, Stmt::For(None, None, None, _)));
+    }
+
+    #[test]
+    fn test_declared_names_are_interned() {
+        let tokens = crate::scanner::Scanner::new("fun f(a) {} var a = f;").run();
+        let mut parser = Parser::new(&tokens);
+        let fun = declaration(&mut parser).unwrap().value;
+        let var = declaration(&mut parser).unwrap().value;
+        match (fun, var) {
+            (Stmt::Function(_, params, _), Stmt::Var(name, _)) => assert!(std::rc::Rc::ptr_eq(&params[0].value, &name.value)),
+            other => panic!("Expected a function and a variable, got {:?}", other),
+        }
+    }
+}
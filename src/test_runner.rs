@@ -0,0 +1,360 @@
+//! Golden-output tests for lux scripts.
+//!
+//! A script states what it should print with `// expect: ...` comments, one
+//! per printed line and in order. A script that should fail states the error
+//! with `// expect error: ...`; the actual error must contain that text.
+//! Output too long for comments goes in a `.out` file next to the script,
+//! which is expected after any `// expect: ...` lines.
+//!
+//! After the script ran, every top level function named `test_*` is called
+//! as a separate test case, each starting from the state the script left.
+//! A test case fails if it raises an error, usually from `assert` or `assertEqual`.
+//!
+//! With coverage, every script records which of its statements and branches
+//! its own run and its test cases ran together.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    ast::Stmt,
+    check,
+    interpreter::{coverage::FileCoverage, Coverage, Interpreter, LuxValue, Output, RuntimeError},
+    optimizer,
+    position::LineOffsets,
+    program::Program,
+    resolver::Resolver,
+    scanner::Scanner,
+};
+
+const EXPECT: &str = "// expect: ";
+const EXPECT_ERROR: &str = "// expect error: ";
+const TEST_PREFIX: &str = "test_";
+
+/// The result of one test case. It passed if there are no failures.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub name: String,
+    pub failures: Vec<String>,
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub outcomes: Vec<Outcome>,
+    /// One per script, if coverage was asked for.
+    pub coverage: Vec<FileCoverage>,
+}
+
+impl Report {
+    pub fn passed(&self) -> usize {
+        self.outcomes.iter().filter(|o| o.passed()).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.outcomes.len() - self.passed()
+    }
+
+    /// One line per case, the reasons a case failed, and a summary.
+    pub fn print<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for outcome in &self.outcomes {
+            if outcome.passed() {
+                writeln!(out, "PASS {}", outcome.name)?;
+            } else {
+                writeln!(out, "FAIL {}", outcome.name)?;
+                for failure in &outcome.failures {
+                    writeln!(out, "    {}", failure)?;
+                }
+            }
+        }
+        writeln!(out, "\n{} passed, {} failed", self.passed(), self.failed())
+    }
+}
+
+/// What a script expects to print and which errors it expects, taken from its comments.
+#[derive(Debug, Default, PartialEq)]
+pub struct Expectations {
+    pub output: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+impl Expectations {
+    pub fn parse(source: &str) -> Self {
+        let mut scanner = Scanner::new(source);
+        scanner.run();
+
+        let mut expectations = Expectations::default();
+        for comment in scanner.comments() {
+            if let Some(error) = comment.value.strip_prefix(EXPECT_ERROR) {
+                expectations.errors.push(error.trim().to_string());
+            } else if let Some(line) = comment.value.strip_prefix(EXPECT) {
+                expectations.output.push(line.trim_end().to_string());
+            }
+        }
+        expectations
+    }
+
+    /// Expect every line of `output` after the lines already expected.
+    pub fn expect_output(&mut self, output: &str) {
+        self.output.extend(output.lines().map(str::to_string));
+    }
+}
+
+/// Run a script and compare what it does with its expectations, then run its test functions.
+///
+/// The first outcome is the script itself, followed by one per test function.
+pub fn run_source(name: &str, source: &str) -> Vec<Outcome> {
+    run_source_with(name, source, None)
+}
+
+/// Like `run_source`, recording what ran in `coverage`.
+pub fn run_source_with(name: &str, source: &str, coverage: Option<&Coverage>) -> Vec<Outcome> {
+    run_expecting(name, source, &Expectations::parse(source), coverage)
+}
+
+fn run_expecting(name: &str, source: &str, expected: &Expectations, coverage: Option<&Coverage>) -> Vec<Outcome> {
+    let output = Output::captured();
+    let (errors, tests) = match run_script(source, output.clone(), coverage) {
+        Ok((interpreter, tests)) => (vec![], Some((interpreter, tests))),
+        Err(errors) => (errors, None),
+    };
+    let printed = output.take();
+    let printed: Vec<&str> = printed.lines().collect();
+
+    let mut failures = Vec::new();
+    for (i, line) in printed.iter().enumerate() {
+        match expected.output.get(i) {
+            Some(expected) if expected == line => {}
+            Some(expected) => failures.push(format!("expected `{}` on output line {}, got `{}`", expected, i + 1, line)),
+            None => failures.push(format!("unexpected output `{}`", line)),
+        }
+    }
+    for expected in expected.output.iter().skip(printed.len()) {
+        failures.push(format!("missing output `{}`", expected));
+    }
+
+    for (i, error) in errors.iter().enumerate() {
+        match expected.errors.get(i) {
+            Some(expected) if error.contains(expected.as_str()) => {}
+            Some(expected) => failures.push(format!("expected error `{}`, got `{}`", expected, error)),
+            None => failures.push(format!("unexpected error `{}`", error)),
+        }
+    }
+    for expected in expected.errors.iter().skip(errors.len()) {
+        failures.push(format!("missing error `{}`", expected));
+    }
+
+    let mut outcomes = vec![Outcome { name: name.to_string(), failures }];
+    if let Some((interpreter, tests)) = tests {
+        let line_offsets = LineOffsets::new(source);
+        let snapshot = interpreter.snapshot();
+        for test in tests {
+            let mut interpreter = snapshot.interpreter();
+            let failures = match run_test(&mut interpreter, &test) {
+                Ok(()) => vec![],
+                Err(err) => vec![runtime_error(&line_offsets, &interpreter, err)],
+            };
+            // Tests are judged by their asserts, not by what they print.
+            output.take();
+            outcomes.push(Outcome { name: format!("{}::{}", name, test), failures });
+        }
+    }
+    outcomes
+}
+
+/// Run a script printing to `output`. Returns the interpreter it ran in and
+/// the names of its test functions, or the errors found before running it
+/// or the runtime error that stopped it.
+fn run_script(source: &str, output: Output, coverage: Option<&Coverage>) -> Result<(Interpreter, Vec<String>), Vec<String>> {
+    let line_offsets = LineOffsets::new(source);
+    let diagnostics = check::check(source);
+    if !diagnostics.is_empty() {
+        return Err(diagnostics
+            .iter()
+            .map(|d| format!("line {}: {}", line_offsets.line(d.span.start), d.message))
+            .collect());
+    }
+
+    let tokens = Scanner::new(source).run();
+    let mut program = Program::parse(&tokens).expect("checked programs parse");
+    if coverage.is_none() {
+        program = optimizer::optimize(program);
+    }
+    let mut interpreter = Interpreter::new();
+    interpreter.set_output(output);
+    interpreter.set_coverage(coverage.cloned());
+    Resolver::new().run(&program).expect("checked programs resolve");
+    if let Err(err) = interpreter.run(&program) {
+        return Err(vec![runtime_error(&line_offsets, &interpreter, err)]);
+    }
+
+    let tests = program
+        .statements
+        .iter()
+        .filter_map(|stmt| match &stmt.value {
+            Stmt::Function(name, _, _) if name.value.starts_with(TEST_PREFIX) => Some(name.value.to_string()),
+            _ => None,
+        })
+        .collect();
+    Ok((interpreter, tests))
+}
+
+fn run_test(interpreter: &mut Interpreter, name: &str) -> Result<(), RuntimeError> {
+    match interpreter.globals().get(name) {
+        Some(LuxValue::Callable(test)) if test.arity() == 0 => test.call(interpreter, &[]).map(|_| ()),
+        Some(LuxValue::Callable(_)) => Err(RuntimeError::TypeError(format!(
+            "Test function `{}` must not take arguments",
+            name
+        ))),
+        _ => Err(RuntimeError::UndefinedVariable(name.to_string())),
+    }
+}
+
+fn runtime_error(line_offsets: &LineOffsets, interpreter: &Interpreter, err: RuntimeError) -> String {
+    format!("line {}: {:?}", line_offsets.line(interpreter.error_span().start), err)
+}
+
+pub fn run_file(path: &Path) -> io::Result<Vec<Outcome>> {
+    let source = fs::read_to_string(path)?;
+    let expected = file_expectations(path, &source)?;
+    Ok(run_expecting(&path.display().to_string(), &source, &expected, None))
+}
+
+/// The expectations in a script's comments and in its `.out` file, if it has one.
+fn file_expectations(path: &Path, source: &str) -> io::Result<Expectations> {
+    let mut expected = Expectations::parse(source);
+    match fs::read_to_string(path.with_extension("out")) {
+        Ok(output) => expected.expect_output(&output),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+    Ok(expected)
+}
+
+/// Like `run_file`, also returning what ran of the script if it parses.
+pub fn run_file_with_coverage(path: &Path) -> io::Result<(Vec<Outcome>, Option<FileCoverage>)> {
+    let source = fs::read_to_string(path)?;
+    let name = path.display().to_string();
+    let expected = file_expectations(path, &source)?;
+    let coverage = Coverage::new();
+    let outcomes = run_expecting(&name, &source, &expected, Some(&coverage));
+    let program = Program::parse(&Scanner::new(&source).run()).ok();
+    Ok((outcomes, program.map(|program| coverage.file(&name, &source, &program))))
+}
+
+/// The `.lux` files given, searching directories (not recursively).
+pub fn scripts<P: AsRef<Path>>(paths: &[P]) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        let path = path.as_ref();
+        if path.is_dir() {
+            let mut scripts = Vec::new();
+            for entry in fs::read_dir(path)? {
+                let script = entry?.path();
+                if script.extension().is_some_and(|e| e == "lux") {
+                    scripts.push(script);
+                }
+            }
+            scripts.sort();
+            files.extend(scripts);
+        } else {
+            files.push(path.to_path_buf());
+        }
+    }
+    Ok(files)
+}
+
+/// Run every `.lux` file given, searching directories (not recursively).
+pub fn run_paths<P: AsRef<Path>>(paths: &[P], coverage: bool) -> io::Result<Report> {
+    let files = scripts(paths)?;
+    let mut report = Report::default();
+    for file in files {
+        if coverage {
+            let (outcomes, file) = run_file_with_coverage(&file)?;
+            report.outcomes.extend(outcomes);
+            report.coverage.extend(file);
+        } else {
+            report.outcomes.extend(run_file(&file)?);
+        }
+    }
+    Ok(report)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_expectations() {
+        let source = "print 1; // expect: 1\n// expect error: Boom\nprint \"a b\"; // expect: a b";
+        let expectations = Expectations::parse(source);
+        assert_eq!(expectations.output, vec!["1", "a b"]);
+        assert_eq!(expectations.errors, vec!["Boom"]);
+    }
+
+    #[test]
+    fn test_reports_wrong_and_missing_output() {
+        let outcome = &run_source("t", "print 1; // expect: 2\n// expect: 3")[0];
+        assert_eq!(
+            outcome.failures,
+            vec!["expected `2` on output line 1, got `1`", "missing output `3`"]
+        );
+    }
+
+    #[test]
+    fn test_expected_errors() {
+        assert!(run_source("t", "print 1 / 0; // expect error: DivideByZero")[0].passed());
+        assert!(run_source("t", "print 1 // expect error: line 1: Expected ;")[0].passed());
+        let outcome = &run_source("t", "print 1;\nprint a;")[0];
+        assert_eq!(outcome.failures, vec![
+            "unexpected output `1`",
+            "unexpected error `line 2: UndefinedVariable(\"a\")`",
+        ]);
+    }
+
+    #[test]
+    fn test_functions_are_separate_cases() {
+        let source = "\
+var count = 0;
+fun test_passes() {
+  count = count + 1;
+  assertEqual(count, 1);
+}
+fun test_starts_from_script_state() {
+  assertEqual(count, 0);
+}
+fun test_fails() {
+  assert(false, \"boom\");
+}
+fun helper() {}
+";
+        let outcomes = run_source("t", source);
+        let names: Vec<_> = outcomes.iter().map(|o| o.name.as_str()).collect();
+        assert_eq!(names, vec!["t", "t::test_passes", "t::test_starts_from_script_state", "t::test_fails"]);
+        assert!(outcomes[1].passed());
+        assert!(outcomes[2].passed());
+        assert_eq!(outcomes[3].failures, vec!["line 10: AssertionFailed(\"boom\")"]);
+    }
+
+    #[test]
+    fn test_out_file_follows_comment_expectations() {
+        let dir = std::env::temp_dir().join(format!("rlux-out-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("long.lux");
+        fs::write(&script, "print 1; // expect: 1\nfor (var i = 2; i < 5; i = i + 1) print i;").unwrap();
+        fs::write(dir.join("long.out"), "2\n3\n4\n").unwrap();
+        assert!(run_file(&script).unwrap()[0].passed());
+
+        fs::write(dir.join("long.out"), "2\n3\n").unwrap();
+        let outcome = &run_file(&script).unwrap()[0];
+        assert_eq!(outcome.failures, vec!["unexpected output `4`"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
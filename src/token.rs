@@ -1,5 +1,11 @@
 use std::fmt::Display;
 
+/// The reserved words of lux.
+pub const KEYWORDS: [&str; 16] = [
+    "and", "class", "else", "false", "for", "fun", "if", "nil", "or", "print", "return", "super",
+    "this", "true", "var", "while",
+];
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
     // Single-character tokens.
@@ -0,0 +1,11 @@
+use rlux::test_runner;
+
+/// The scripts directory doubles as the language's golden test suite.
+#[test]
+fn test_scripts() {
+    let report = test_runner::run_paths(&[concat!(env!("CARGO_MANIFEST_DIR"), "/scripts")], false).unwrap();
+    let mut out = Vec::new();
+    report.print(&mut out).unwrap();
+    assert_eq!(report.failed(), 0, "{}", String::from_utf8(out).unwrap());
+    assert!(report.passed() > 0);
+}